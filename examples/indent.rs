@@ -0,0 +1,45 @@
+#[macro_use] extern crate sylo;
+
+use sylo::lang::re::{self, RegEx};
+
+use std::time::Instant;
+
+fn main() {
+    let timer = Instant::now();
+
+    let def = parser_def! {
+        lexer: {
+            [skip] _ws: re::any(" \t\n").plus(),
+            word:       re::range('a', 'z').plus(),
+            colon:      re::literal(":"),
+            lparen:     re::literal("("),
+            rparen:     re::literal(")"),
+            INDENT:     RegEx::none(),
+            DEDENT:     RegEx::none(),
+            NEWLINE:    RegEx::none(),
+        },
+        layout: {
+            indent: INDENT, dedent: DEDENT, newline: NEWLINE,
+            tab_width: 4,
+            brackets: [(lparen, rparen)],
+        },
+        parser: {
+            Program          : Statements,
+            [skip] Statements : Statements Statement
+                             | Statement,
+            Statement        : word colon NEWLINE INDENT Statements DEDENT
+                             | Words NEWLINE,
+            [skip] Words     : Words Item
+                             | Item,
+            [skip] Item      : word
+                             | lparen Words rparen,
+        }
+    };
+
+    let parser = def.compile().unwrap();
+
+    let cst = parser.cst("ready:\n    say (hello\n  world)\n    repeat:\n\t  nod\nsleep\n").unwrap();
+    std::fs::write("_graph.dot", cst.dot(&parser)).unwrap();
+
+    println!("Indentation lexer-parser compiled in {:?}.", timer.elapsed());
+}
\ No newline at end of file
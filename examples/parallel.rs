@@ -0,0 +1,29 @@
+#[macro_use] extern crate sylo;
+
+use sylo::lang::re;
+
+use std::thread;
+use std::time::Instant;
+
+fn main() {
+    let lexer = lex_def! {
+        [skip] _ws:     re::any(" \t\r\n").plus(),
+        [skip] comment: nested("/*", "*/"),
+        string:         re::literal("\"").then(&re::range(' ', '!').or(&re::range('#', '~')).star()).then(&re::literal("\"")),
+        ident:          re::range('a', 'z').or(&re::literal("_")).plus(),
+        number:         re::range('0', '9').plus(),
+        punct:          re::any("{}();=+*/<>,.&|!"),
+    }.1.compile();
+
+    let source = "fn main() {\n    /* count to ten */\n    let total = 0;\n    for i in 0..10 { total = total + i * 2; }\n    print(\"total: {}\", total);\n}\n";
+    let text = source.repeat(200_000);
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+
+    let timer = Instant::now();
+    let count = lexer.parse(&text).recover().count();
+    println!("Lexed {} tokens in {:?}.", count, timer.elapsed());
+
+    let timer = Instant::now();
+    let count = lexer.parse_parallel(&text, threads).len();
+    println!("Lexed {} tokens on {} threads in {:?}.", count, threads, timer.elapsed());
+}
\ No newline at end of file
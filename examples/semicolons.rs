@@ -0,0 +1,53 @@
+#[macro_use] extern crate sylo;
+
+use sylo::lang::re::{self, RegEx};
+
+use std::time::Instant;
+
+fn main() {
+    let timer = Instant::now();
+
+    let def = parser_def! {
+        lexer: {
+            [skip] _ws:      re::any(" \t\n").plus(),
+            [skip] _comment: re::literal("//").then(&re::range(' ', '~').star()),
+            ident:           re::range('a', 'z').plus(),
+            int:             re::range('0', '9').plus(),
+            assign:          re::literal("="),
+            gt:              re::literal(">"),
+            shr:             RegEx::none(),
+            lparen:          re::literal("("),
+            rparen:          re::literal(")"),
+            lbrace:          re::literal("{"),
+            rbrace:          re::literal("}"),
+            semi:            re::literal(";"),
+        },
+        rewrite: {
+            insert semi after [ident, int, rparen, rbrace],
+            drop semi before [rbrace],
+            merge gt gt into shr,
+        },
+        parser: {
+            Program          : Statements,
+            [skip] Statements : Statements Statement semi
+                             | Statement semi,
+            [skip] Body      : Statements Statement
+                             | Statement,
+            Statement        : ident assign Expr
+                             | ident lparen rparen lbrace Body rbrace
+                             | ident lparen rparen lbrace rbrace,
+            Expr             : Expr shr Operand
+                             | Expr gt Operand
+                             | Operand,
+            [skip] Operand   : ident
+                             | int,
+        }
+    };
+
+    let parser = def.compile().unwrap();
+
+    let cst = parser.cst("x = 1 // one\ny = x >> 2; z = y > x\nloop() {\n    x = y\n}\n").unwrap();
+    std::fs::write("_graph.dot", cst.dot(&parser)).unwrap();
+
+    println!("Semicolon-inserting lexer-parser compiled in {:?}.", timer.elapsed());
+}
\ No newline at end of file
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use crate::cst::CSTNode;
+use crate::lang::lex::{Span, Token};
+use crate::lang::parser::Parser;
+
+/// Category of highlighted text, as found in most editor themes.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Category {
+    Keyword,
+    Identifier,
+    Function,
+    Type,
+    Constant,
+    Number,
+    String,
+    Comment,
+    Operator,
+    Punctuation,
+    /// Text that could not be lexed.
+    Error,
+}
+
+impl Category {
+    /// Returns the name of the category, used as the class of html spans.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Keyword     => "keyword",
+            Category::Identifier  => "identifier",
+            Category::Function    => "function",
+            Category::Type        => "type",
+            Category::Constant    => "constant",
+            Category::Number      => "number",
+            Category::String      => "string",
+            Category::Comment     => "comment",
+            Category::Operator    => "operator",
+            Category::Punctuation => "punctuation",
+            Category::Error       => "error",
+        }
+    }
+
+    /// Returns the SGR parameters of the terminal style of the category.
+    #[must_use]
+    pub fn ansi(self) -> &'static str {
+        match self {
+            Category::Keyword     => "1;35",
+            Category::Identifier  => "39",
+            Category::Function    => "34",
+            Category::Type        => "33",
+            Category::Constant
+            | Category::Number    => "36",
+            Category::String      => "32",
+            Category::Comment     => "90",
+            Category::Operator
+            | Category::Punctuation => "37",
+            Category::Error       => "4;31",
+        }
+    }
+}
+
+/// Assigns categories to the text lexed and parsed by a parser. Tokens take
+/// the category of their class, unless enclosed in the CST by variables with
+/// categories, of which the innermost wins.
+pub struct Highlighter<'a> {
+    parser:  &'a Parser,
+    classes: Vec<Option<Category>>,
+    vars:    Vec<Option<Category>>,
+}
+
+impl<'a> Highlighter<'a> {
+    #[must_use]
+    pub fn new(parser: &'a Parser) -> Self {
+        Self {
+            parser,
+            classes: vec![None; parser.lex_labels.len()],
+            vars: vec![None; parser.syn_labels.len()],
+        }
+    }
+
+    /// Highlights tokens of the class labelled `label`, which may be skipped.
+    ///
+    /// # Panics
+    /// Panics if the parser has no such token class.
+    #[must_use]
+    pub fn class(mut self, label: &str, category: Category) -> Self {
+        let class = self.parser.lex_labels.iter().position(|other| other == label)
+            .unwrap_or_else(|| panic!("undefined token class {}", label));
+        self.classes[class] = Some(category);
+        self
+    }
+
+    /// Highlights tokens within variables labelled `label`.
+    ///
+    /// # Panics
+    /// Panics if the parser has no such variable.
+    #[must_use]
+    pub fn var(mut self, label: &str, category: Category) -> Self {
+        let var = self.parser.syn_labels.iter().position(|other| other == label)
+            .unwrap_or_else(|| panic!("undefined variable {}", label));
+        self.vars[var] = Some(category);
+        self
+    }
+
+    /// Splits `text` into spans with their categories, covering all of it.
+    /// Text between tokens has no category, and neither do tokens of classes
+    /// without one. Variables only apply if `text` parses, so text with syntax
+    /// errors is still highlighted by token class.
+    #[must_use]
+    pub fn spans(&self, text: &str) -> Vec<(Span, Option<Category>)> {
+        // lex once, setting skipped tokens and errors apart from the tokens seen by the parser
+        let mut parse = self.parser.lex.parse(text).recover().skipped();
+        let mut regions = Vec::new();
+        let mut tokens = Vec::new();
+        let mut lexed = true;
+
+        while let Some(item) = parse.next() {
+            match item {
+                Ok(token) if parse.was_skipped() => regions.push((token.span, self.classes[token.class])),
+                Ok(token) => tokens.push(token),
+                Err(error) => {
+                    lexed = false;
+                    regions.push((error.unlexable, Some(Category::Error)));
+                },
+            }
+        }
+
+        // rewrite the tokens as the parser does, so variables apply to the tokens they contain
+        let (tokens, by_var) = match self.parser.rewrite(text, tokens.iter().copied().map(Ok)) {
+            Ok(rewritten) if lexed => {
+                let by_var = self.var_categories(&rewritten);
+                (rewritten, by_var)
+            },
+            Ok(rewritten) => (rewritten, HashMap::new()),
+            Err(_) => (tokens, HashMap::new()),
+        };
+        regions.extend(tokens.iter().map(|token| (token.span, by_var.get(&token.span.start).copied().or(self.classes[token.class]))));
+        regions.sort_by_key(|(span, _)| span.start);
+
+        let mut spans: Vec<(Span, Option<Category>)> = Vec::new();
+        let mut push = |span: Span, category| match spans.last_mut() {
+            Some((last, last_category)) if *last_category == category => last.end = span.end,
+            _ => spans.push((span, category)),
+        };
+
+        let mut index = 0;
+        for (span, category) in regions {
+            if span.start > index {
+                push(Span { start: index, end: span.start }, None);
+            }
+            if span.end > span.start {
+                push(span, category);
+                index = span.end;
+            }
+        }
+
+        if index < text.len() {
+            push(Span { start: index, end: text.len() }, None);
+        }
+
+        spans
+    }
+
+    /// Renders `text` for terminals, styling each category with ANSI escape
+    /// codes.
+    #[must_use]
+    pub fn ansi(&self, text: &str) -> String {
+        self.spans(text).into_iter().map(|(span, category)| match category {
+            Some(category) => format!("\x1b[{}m{}\x1b[0m", category.ansi(), &text[span.start..span.end]),
+            None => text[span.start..span.end].to_string(),
+        }).collect()
+    }
+
+    /// Renders `text` as html, with each category in a `<span>` classed by the
+    /// category's name. The html is meant to be placed in a `<pre>` element.
+    #[must_use]
+    pub fn html(&self, text: &str) -> String {
+        self.spans(text).into_iter().map(|(span, category)| match category {
+            Some(category) => format!("<span class=\"{}\">{}</span>", category.name(), escape(&text[span.start..span.end])),
+            None => escape(&text[span.start..span.end]),
+        }).collect()
+    }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+impl Highlighter<'_> {
+    /// Returns the categories of tokens within variables with categories, by
+    /// start index, if the tokens parse.
+    fn var_categories(&self, tokens: &[Token]) -> HashMap<usize, Category> {
+        let mut categories = HashMap::new();
+
+        // an empty CST has no root
+        if self.vars.iter().all(Option::is_none) || tokens.is_empty() {
+            return categories;
+        }
+        let Ok(cst) = self.parser.build(tokens.to_vec()) else {
+            return categories;
+        };
+
+        let mut stack = vec![(cst.root(), None)];
+        while let Some((id, category)) = stack.pop() {
+            match id.to_node(&cst) {
+                CSTNode::Leaf(leaf) => {
+                    let token = leaf.token(&cst);
+                    if let (Some(category), false) = (category, token.lexeme.is_empty()) {
+                        categories.insert(token.span.start, category);
+                    }
+                },
+                CSTNode::Branch(branch) => {
+                    let category = self.vars[branch.var].or(category);
+                    stack.extend(branch.children(&cst).map(|child| (child, category)));
+                },
+            }
+        }
+
+        categories
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Category, Highlighter};
+    use crate::lang::re::{self, RegEx};
+
+    #[test]
+    fn highlight() {
+        let parser = parser_def! {
+            lexer: {
+                [skip] _ws:      re::any(" \n").plus(),
+                [skip] _comment: re::literal("#").then(&re::range(' ', '~').star()),
+                kw_let:          re::literal("let"),
+                ident:           re::range('a', 'z').plus(),
+                int:             re::range('0', '9').plus(),
+                eq:              re::literal("="),
+                lt:              re::literal("<"),
+                semi:            re::literal(";"),
+            },
+            parser: {
+                Program           : Statements,
+                [skip] Statements : Statements Statement
+                                  | Statement,
+                Statement         : kw_let ident eq Value semi,
+                Value             : Value lt Operand
+                                  | Operand,
+                [skip] Operand    : ident
+                                  | int,
+            }
+        }.compile().unwrap();
+
+        let highlighter = Highlighter::new(&parser)
+            .class("_comment", Category::Comment)
+            .class("kw_let", Category::Keyword)
+            .class("ident", Category::Identifier)
+            .class("int", Category::Number)
+            .class("lt", Category::Operator)
+            .var("Value", Category::Constant);
+
+        let text = "let x = 1; # one\nlet y = x<2;";
+        let spans: Vec<_> = highlighter.spans(text).into_iter().map(|(span, category)| (&text[span.start..span.end], category)).collect();
+        assert_eq!(spans, vec![
+            ("let", Some(Category::Keyword)),
+            (" ", None),
+            ("x", Some(Category::Identifier)),
+            (" = ", None),
+            ("1", Some(Category::Constant)),
+            ("; ", None),
+            ("# one", Some(Category::Comment)),
+            ("\n", None),
+            ("let", Some(Category::Keyword)),
+            (" ", None),
+            ("y", Some(Category::Identifier)),
+            (" = ", None),
+            ("x<2", Some(Category::Constant)),
+            (";", None),
+        ]);
+
+        // variables are ignored when the text does not parse
+        let text = "let y = x<2 @";
+        assert_eq!(highlighter.ansi(text), "\x1b[1;35mlet\x1b[0m \x1b[39my\x1b[0m = \x1b[39mx\x1b[0m\x1b[37m<\x1b[0m\x1b[36m2\x1b[0m \x1b[4;31m@\x1b[0m");
+        assert_eq!(highlighter.html(text), "<span class=\"keyword\">let</span> <span class=\"identifier\">y</span> = \
+            <span class=\"identifier\">x</span><span class=\"operator\">&lt;</span><span class=\"number\">2</span> <span class=\"error\">@</span>");
+
+        assert!(highlighter.spans("").is_empty());
+    }
+
+    #[test]
+    fn rewritten_tokens() {
+        let parser = parser_def! {
+            lexer: {
+                [skip] _ws: re::any(" \n").plus(),
+                ident:      re::range('a', 'z').plus(),
+                lt:         re::literal("<"),
+                shl:        RegEx::none(),
+                semi:       re::literal(";"),
+            },
+            rewrite: {
+                insert semi after [ident],
+                merge lt lt into shl,
+            },
+            parser: {
+                Program           : Statements,
+                [skip] Statements : Statements Statement
+                                  | Statement,
+                Statement         : Value semi,
+                Value             : Value Shift ident
+                                  | ident,
+                Shift             : shl,
+            }
+        }.compile().unwrap();
+
+        let highlighter = Highlighter::new(&parser)
+            .class("lt", Category::Punctuation)
+            .var("Shift", Category::Operator);
+
+        // merged tokens take the categories of the variables they are parsed in
+        let text = "a << b\nc";
+        let spans: Vec<_> = highlighter.spans(text).into_iter().map(|(span, category)| (&text[span.start..span.end], category)).collect();
+        assert_eq!(spans, vec![("a ", None), ("<<", Some(Category::Operator)), (" b\nc", None)]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::lang::re::DFA;
+use super::{LexDef, ModeChange};
+
+/// Problem with a lexer definition, found by `LexDef::analyze`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// The class matches the empty string, so lexing could stop making progress.
+    Nullable { class: usize },
+    /// The class is never matched, as every string it matches is won by an
+    /// earlier rule, or it is only active in modes that are never entered.
+    Unreachable { class: usize },
+    /// Both classes match `example` in some mode, where the first class wins.
+    Overlap { classes: (usize, usize), example: String },
+}
+
+impl Diagnostic {
+    /// Checks if the problem makes the lexer unusable, rather than likely unintended.
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        matches!(self, Diagnostic::Nullable { .. })
+    }
+
+    /// Describes the problem, naming token classes with `labels`.
+    #[must_use]
+    pub fn message<T: AsRef<str>>(&self, labels: &[T]) -> String {
+        match self {
+            Diagnostic::Nullable { class } => {
+                format!("{} matches the empty string", labels[*class].as_ref())
+            },
+            Diagnostic::Unreachable { class } => {
+                format!("{} is never matched", labels[*class].as_ref())
+            },
+            Diagnostic::Overlap { classes: (a, b), example } => {
+                format!("{} and {} both match {:?}, which lexes as {}", labels[*a].as_ref(), labels[*b].as_ref(), example, labels[*a].as_ref())
+            },
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max = match self {
+            Diagnostic::Nullable { class } | Diagnostic::Unreachable { class } => *class,
+            Diagnostic::Overlap { classes: (a, b), .. } => *a.max(b),
+        };
+        let labels = (0..=max).map(|class| format!("class {class}")).collect::<Vec<_>>();
+        f.write_str(&self.message(&labels))
+    }
+}
+
+impl LexDef {
+    /// Finds nullable, unreachable and overlapping rules. Overlaps come with
+    /// the shortest example string both rules match. Nullable rules are errors,
+    /// as the lexer would emit empty tokens without consuming input.
+    #[must_use]
+    pub fn analyze(&self) -> Vec<Diagnostic> {
+        let mut nullable = vec![false; self.regexes.len()];
+        let mut winners  = vec![Vec::new(); self.modes.len()]; // classes that win some match in each mode
+        let mut overlaps = BTreeMap::new();
+
+        for (mode, classes) in self.modes.iter().enumerate() {
+            let dfa = DFA::from(classes.iter().map(|&class| &self.regexes[class]));
+
+            for (id, example) in shortest_examples(&dfa) {
+                // classes are in order of priority within the mode
+                let classes = dfa.classes(id).iter().map(|&class| self.modes[mode][class]).collect::<Vec<_>>();
+
+                if let Some(&winner) = classes.first() {
+                    winners[mode].push(winner);
+                }
+
+                if example.is_empty() {
+                    for &class in &classes {
+                        nullable[class] = true;
+                    }
+                }
+
+                for (i, &a) in classes.iter().enumerate() {
+                    for &b in &classes[i + 1..] {
+                        overlaps.entry((a, b)).or_insert_with(|| String::from_utf8_lossy(&example).into_owned());
+                    }
+                }
+            }
+        }
+
+        // only modes entered by winning classes can match anything
+        let mut reachable = vec![false; self.regexes.len()];
+        let mut entered = vec![false; self.modes.len()];
+        let mut stack = vec![0];
+        entered[0] = true;
+
+        while let Some(mode) = stack.pop() {
+            for &class in &winners[mode] {
+                reachable[class] = true;
+                if let Some(ModeChange::Push(next) | ModeChange::Switch(next)) = self.mode_changes[class] {
+                    if !entered[next] {
+                        entered[next] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+
+        // pseudo-tokens are never matched by design
+        if let Some(layout) = &self.layout {
+            for class in [layout.indent, layout.dedent, layout.newline] {
+                reachable[class] = true;
+            }
+        }
+
+        let mut diagnostics = Vec::new();
+
+        for class in 0..self.regexes.len() {
+            if nullable[class] {
+                diagnostics.push(Diagnostic::Nullable { class });
+            }
+            if !reachable[class] {
+                diagnostics.push(Diagnostic::Unreachable { class });
+            }
+        }
+
+        diagnostics.extend(overlaps.into_iter().map(|(classes, example)| Diagnostic::Overlap { classes, example }));
+        diagnostics
+    }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+/// Returns each state reachable from the start of `dfa` with the shortest
+/// string leading to it, in order of increasing length.
+fn shortest_examples(dfa: &DFA) -> Vec<(usize, Vec<u8>)> {
+    let mut visited = vec![false; dfa.states().len()];
+    let mut queue = vec![(1, Vec::new())];
+
+    visited[0] = true; // sink
+    visited[1] = true;
+
+    let mut i = 0;
+    while i < queue.len() {
+        let (id, example) = queue[i].clone();
+        for symbol in 0..=u8::MAX {
+            let next_id = dfa.step(id, symbol);
+            if !visited[next_id] {
+                visited[next_id] = true;
+                let mut next_example = example.clone();
+                next_example.push(symbol);
+                queue.push((next_id, next_example));
+            }
+        }
+        i += 1;
+    }
+
+    queue
+}
\ No newline at end of file
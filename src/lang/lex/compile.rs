@@ -1,33 +1,98 @@
-use crate::lang::re::{RegEx, DFA};
-use super::{LexAnalyzer, Command};
+use crate::lang::re::{RegEx, DFA, Encoding};
+use super::{LexAnalyzer, Command, ModeChange, Converter, Layout, Nested, Scanner};
+use std::convert::TryFrom;
+
+/// Maximum number of exiting bytes for a state to be accelerated.
+const MAX_ACCEL_EXITS: usize = 3;
 
 pub struct LexDef {
-    pub regexes:  Vec<RegEx>,
-    pub commands: Vec<Command>,
+    pub regexes:      Vec<RegEx>,
+    pub commands:     Vec<Command>,
+    pub modes:        Vec<Vec<usize>>,           // classes active in each mode, the first mode is initial
+    pub mode_changes: Vec<Option<ModeChange>>,   // mode change on matching each class
+    pub converters:   Vec<Option<Converter>>,    // converter of the lexemes of each class to values
+    pub nested:       Vec<Option<Nested>>,       // delimiters of each class that nests
+    pub scanners:     Vec<Option<Scanner>>,      // scanners that take over from the prefix matched by each class
+    pub layout:       Option<Layout>,            // classes of indentation pseudo-tokens
 }
 
 impl LexDef {
     #[must_use]
     pub fn compile(&self) -> LexAnalyzer {
-        let dfa = DFA::from(&self.regexes).minimize();
-        
-        let nrows = dfa.states().len() - 1; // excluding sink
+        self.compile_with(Encoding::Utf8)
+    }
+
+    /// Compiles a lexer that consumes input in the given encoding. Each mode
+    /// gets its own dfa, and all dfas share a single table and sink state.
+    #[must_use]
+    pub fn compile_with(&self, encoding: Encoding) -> LexAnalyzer {
+        let dfas: Vec<_> = self.modes.iter().map(|classes| {
+            let dfa = DFA::from(classes.iter().map(|&class| &self.regexes[class]));
+            match encoding {
+                Encoding::Utf8 => dfa.minimize(),
+                _              => dfa.minimize().encode(encoding).minimize(),
+            }
+        }).collect();
+
+        // row of the start state of each mode, excluding sinks
+        let mut starts = Vec::with_capacity(dfas.len());
+        let mut nrows = 0;
+        for dfa in &dfas {
+            starts.push(nrows);
+            nrows += dfa.states().len() - 1;
+        }
+
         let mut next = vec![nrows; 256 * nrows];
-        for (row, state) in dfa.states().iter().skip(1).enumerate() {
-            for (&symbol, &dest) in &state.next {
-                next[256 * row + symbol as usize] = dest - 1;
+        let mut classes = Vec::with_capacity(nrows + 1);
+        for ((dfa, &start), mode) in dfas.iter().zip(&starts).zip(&self.modes) {
+            for (row, state) in dfa.states().iter().enumerate().skip(1) {
+                for (&symbol, &dest) in &state.next {
+                    if dest != 0 {
+                        next[256 * (start + row - 1) + symbol as usize] = start + dest - 1;
+                    }
+                }
+                classes.push(state.class().map(|class| mode[class]));
             }
         }
-        
-        let classes = dfa.states().iter().skip(1)
-            .map(|state| state.class)
-            .chain(vec![None]) // <-- sink states class
-            .collect();
+        classes.push(None); // <-- sink states class
+
+        // States that loop on all but a few bytes (e.g. inside comments or
+        // strings) are accelerated by scanning for the exit bytes in bulk.
+        // The sink never needs accelerating.
+        let accel = (0..nrows).map(|row| {
+            let exits = (0..=u8::MAX).filter(|&symbol| next[256 * row + symbol as usize] != row).collect::<Vec<_>>();
+            if exits.len() <= MAX_ACCEL_EXITS { Some(exits) } else { None }
+        })
+        .chain(vec![None])
+        .collect();
         
         LexAnalyzer {
             next,
             classes,
+            accel,
+            starts,
             commands: self.commands.to_vec(),
+            mode_changes: self.mode_changes.clone(),
+            converters: self.converters.clone(),
+            nested: self.nested.iter().map(|nested| nested.as_ref().map(|nested| {
+                (encode(&nested.open, encoding), encode(&nested.close, encoding))
+            })).collect(),
+            scanners: self.scanners.clone(),
+            layout: self.layout.clone(),
+            encoding,
         }
     }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+/// Returns the bytes of `text` as fed to the lexer dfa for the given encoding.
+fn encode(text: &str, encoding: Encoding) -> Vec<u8> {
+    match encoding {
+        Encoding::Utf8   => text.as_bytes().to_vec(),
+        Encoding::Latin1 => text.chars().map(|c| u8::try_from(c).expect("delimiter is not Latin-1")).collect(),
+        Encoding::Utf16  => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+    }
 }
\ No newline at end of file
@@ -0,0 +1,78 @@
+use std::fmt;
+use super::{Span, Position};
+
+/// Error produced when no token class matches the input, when a token
+/// matches a rule with an `Error` command or is rejected by an external
+/// scanner, or when the input ends inside a token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Partial token, from where matching started up to where it failed.
+    pub span: Span,
+    /// Position of the start of the partial token.
+    pub start: Position,
+    /// Char that no token could be continued with, or `None` at end of input.
+    pub found: Option<char>,
+    /// Position of the offending char, or of the end of input.
+    pub found_at: Position,
+    /// Token classes that were still viable before matching failed.
+    pub expected: Vec<usize>,
+    /// Span skipped by recovery. When no token matched, it is the first char
+    /// of the partial token, the smallest span that guarantees progress,
+    /// even if tokens can start with that char. When a token
+    /// matched but is unterminated or was rejected (by an `Error` command or
+    /// an external scanner), it is the whole token, same as `span`.
+    pub unlexable: Span,
+    /// Whether the input ended inside a token that more input could
+    /// complete, either where the error is or part way through a longer
+    /// token than one lexed before the error.
+    pub incomplete: bool,
+    /// Message of the `Error` command of the matched token, if any.
+    pub reason: Option<String>,
+}
+
+impl ParseError {
+    /// Checks if the input ended inside a token that more input could
+    /// complete, such as an unterminated string or comment, e.g. so a REPL
+    /// can read another line rather than report an error. With rules `a`
+    /// and `abc`, the input `ab` is incomplete, even though `a` is lexed
+    /// before failing at `b`.
+    #[must_use]
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete
+    }
+
+    /// Describes the error, naming token classes with `labels`.
+    #[must_use]
+    pub fn message<T: AsRef<str>>(&self, labels: &[T]) -> String {
+        let expected = match self.expected.as_slice() {
+            []      => "token".to_string(),
+            [class] => labels[*class].as_ref().to_string(),
+            classes => format!("one of {}", classes.iter().map(|&class| labels[class].as_ref()).collect::<Vec<_>>().join(", ")),
+        };
+
+        if let Some(reason) = &self.reason {
+            return format!("{} at {}", reason, self.start);
+        }
+
+        match self.found {
+            None => {
+                format!("unterminated {} starting at {}", expected, self.start)
+            },
+            Some(c) if self.span.start == self.span.end => {
+                format!("unexpected {:?} at {}", c, self.found_at)
+            },
+            Some(c) => {
+                format!("unexpected {:?} at {} in {} starting at {}", c, self.found_at, expected, self.start)
+            },
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let labels = (0..=self.expected.iter().copied().max().unwrap_or(0)).map(|class| format!("class {class}")).collect::<Vec<_>>();
+        f.write_str(&self.message(&labels))
+    }
+}
+
+impl std::error::Error for ParseError {}
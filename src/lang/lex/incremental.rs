@@ -0,0 +1,186 @@
+use std::ops::Range;
+use super::{LexAnalyzer, Token, ParseError, Span, Position};
+use super::parse::{Parse, Checkpoint};
+
+/// Replacement of the byte range `span` of some text with `len` bytes of new text.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Edit {
+    pub span: Span,
+    pub len:  usize,
+}
+
+/// Tokens of some text, lexed in recovery mode, that are kept up to date with
+/// edits. After an edit, lexing restarts from the first token that read any
+/// edited text, and stops once it lines up with the old tokens again.
+pub struct TokenBuffer<'a> {
+    lex:     &'a LexAnalyzer,
+    entries: Vec<Entry>,
+    tail:    Checkpoint, // state after the last entry
+}
+
+impl<'a> TokenBuffer<'a> {
+    pub(crate) fn new(lex: &'a LexAnalyzer, text: &str) -> Self {
+        let tail = Parse::new(lex, text).checkpoint();
+        let mut buffer = Self { lex, entries: Vec::new(), tail: tail.clone() };
+        buffer.relex(text, tail, Vec::new(), (0, 0));
+        buffer
+    }
+
+    /// Returns the tokens and errors of `text`, the text as of the latest edit.
+    pub fn items<'b>(&'b self, text: &'b str) -> impl Iterator<Item = Result<Token<'b>, &'b ParseError>> {
+        self.entries.iter().map(move |entry| match &entry.item {
+            Ok(Lexed { class, span, start }) => Ok(Token { lexeme: &text[span.start..span.end], class: *class, span: *span, start: *start }),
+            Err(error) => Err(error),
+        })
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Updates the tokens after `edit`, where `text` is the edited text. Returns
+    /// the range of items that were lexed again, which replace all old items
+    /// from the start of the range up to those that were reused.
+    pub fn edit(&mut self, edit: Edit, text: &str) -> Range<usize> {
+        // entries that read no edited text are kept
+        let restart = self.entries.partition_point(|entry| entry.reach <= edit.span.start);
+        let from = self.entries.get(restart).map_or_else(|| self.tail.clone(), |entry| entry.from.clone());
+
+        // entries lexed from past the edit may be reused
+        let mut old = self.entries.split_off(restart);
+        old.retain(|entry| entry.from.index >= edit.span.end);
+
+        let count = self.relex(text, from, old, (edit.span.end, edit.span.start + edit.len));
+        restart..restart + count
+    }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+struct Entry {
+    item:   Result<Lexed, ParseError>,
+    from:   Checkpoint, // state before lexing the entry, including skipped tokens before it
+    extent: usize,      // index past the text read to lex the entry
+    reach:  usize,      // greatest extent of this and all earlier entries
+}
+
+struct Lexed {
+    class: usize,
+    span:  Span,
+    start: Position,
+}
+
+impl TokenBuffer<'_> {
+    /// Lexes `text` from a checkpoint until the end, or until lining up with
+    /// one of the `old` entries, where the old entries from there on (and the
+    /// old tail) are shifted into place. The old entries must have been lexed
+    /// from `old_end` or later, which became `new_end` in `text`. Returns the
+    /// number of entries lexed.
+    fn relex(&mut self, text: &str, from: Checkpoint, old: Vec<Entry>, (old_end, new_end): (usize, usize)) -> usize {
+        let mut parse = Parse::resume(self.lex, text, from);
+        let mut reach = self.entries.last().map_or(0, |entry| entry.reach);
+        let mut count = 0;
+        let mut j = 0;
+
+        loop {
+            let from = parse.checkpoint();
+
+            if from.index >= new_end {
+                // skip old entries lexed from before this point
+                while j < old.len() && old[j].from.index - old_end + new_end < from.index {
+                    j += 1;
+                }
+
+                if j < old.len() {
+                    let shift = Shift { old_end, new_end, old_at: old[j].from.cursor.position, new_at: from.cursor.position };
+                    if shift.checkpoint(old[j].from.clone()) == from {
+                        for entry in old.into_iter().skip(j) {
+                            let entry = shift.entry(entry);
+                            reach = reach.max(entry.extent);
+                            self.entries.push(Entry { reach, ..entry });
+                        }
+                        self.tail = shift.checkpoint(self.tail.clone());
+                        return count;
+                    }
+                }
+            }
+
+            if let Some(item) = parse.next() {
+                let extent = parse.extent();
+                reach = reach.max(extent);
+                self.entries.push(Entry {
+                    item: item.map(|token| Lexed { class: token.class, span: token.span, start: token.start }),
+                    from,
+                    extent,
+                    reach,
+                });
+                count += 1;
+            } else {
+                self.tail = parse.checkpoint();
+                return count;
+            }
+        }
+    }
+}
+
+/// Maps indices and positions from past an edit in the old text to the new
+/// text, given the positions of some point in both texts.
+struct Shift {
+    old_end: usize,
+    new_end: usize,
+    old_at:  Position,
+    new_at:  Position,
+}
+
+impl Shift {
+    fn index(&self, index: usize) -> usize {
+        index - self.old_end + self.new_end
+    }
+
+    fn span(&self, span: Span) -> Span {
+        Span { start: self.index(span.start), end: self.index(span.end) }
+    }
+
+    /// Only the columns of positions on the same line as the known point change.
+    fn position(&self, position: Position) -> Position {
+        if position.line == self.old_at.line {
+            Position { line: self.new_at.line, column: position.column - self.old_at.column + self.new_at.column }
+        } else {
+            Position { line: position.line - self.old_at.line + self.new_at.line, column: position.column }
+        }
+    }
+
+    fn checkpoint(&self, mut checkpoint: Checkpoint) -> Checkpoint {
+        checkpoint.index = self.index(checkpoint.index);
+        checkpoint.cursor.position = self.position(checkpoint.cursor.position);
+        checkpoint
+    }
+
+    fn entry(&self, entry: Entry) -> Entry {
+        let item = match entry.item {
+            Ok(lexed) => Ok(Lexed { class: lexed.class, span: self.span(lexed.span), start: self.position(lexed.start) }),
+            Err(error) => Err(ParseError {
+                span:      self.span(error.span),
+                start:     self.position(error.start),
+                found_at:  self.position(error.found_at),
+                unlexable: self.span(error.unlexable),
+                ..error
+            }),
+        };
+
+        Entry {
+            item,
+            from:   self.checkpoint(entry.from),
+            extent: self.index(entry.extent),
+            reach:  entry.reach,
+        }
+    }
+}
\ No newline at end of file
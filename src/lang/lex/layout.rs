@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+use super::{LexAnalyzer, Token, Parse, ParseError, Span, Position};
+use super::position;
+
+/// Classes of the pseudo-tokens synthesized from indentation, as in Python.
+/// The classes are best defined by rules that match nothing, such as
+/// `RegEx::none()`, and the lexer should skip whitespace and line breaks.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Layout {
+    pub indent:    usize,
+    pub dedent:    usize,
+    pub newline:   usize,
+    pub tab_width: usize,              // tabs indent to the next multiple of this width
+    pub brackets:  Vec<(usize, usize)>, // open and close classes within which lines are joined
+}
+
+/// Tokens of a `Parse` (or other lexed items), with NEWLINE ending each line that has tokens,
+/// followed by INDENT or DEDENTs wherever the indentation changes. Lines are
+/// joined while brackets are open, and all indentation is closed at the end.
+pub struct LayoutParse<'a, I = Parse<'a>> {
+    text:    &'a str,
+    parse:   I,
+    layout:  &'a Layout,
+    recover: bool,
+    done:    bool,
+    levels:  Vec<usize>,                        // widths of the enclosing indentation
+    depth:   usize,                             // number of open brackets
+    end:     Option<(usize, Position)>,         // index and position past the latest token
+    pending: VecDeque<Result<Token<'a>, ParseError>>,
+}
+
+impl<'a, I> LayoutParse<'a, I> {
+    pub(crate) fn new(lex: &'a LexAnalyzer, text: &'a str, parse: I, recover: bool) -> Self {
+        Self {
+            text,
+            parse,
+            layout: lex.layout.as_ref().expect("lexer has no layout"),
+            recover,
+            done: false,
+            levels: vec![0],
+            depth: 0,
+            end: None,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<'a, I: Iterator<Item = Result<Token<'a>, ParseError>>> Iterator for LayoutParse<'a, I> {
+    type Item = Result<Token<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            } else if self.done {
+                return None;
+            }
+
+            match self.parse.next() {
+                Some(Ok(token)) => self.push(token),
+                Some(Err(error)) => return Some(Err(error)),
+                None => {
+                    self.done = true;
+
+                    // close the last line and all indentation
+                    if let Some((index, position)) = self.end {
+                        self.pending.push_back(Ok(pseudo(self.layout.newline, index, position)));
+                        for _ in 1..self.levels.len() {
+                            self.pending.push_back(Ok(pseudo(self.layout.dedent, index, position)));
+                        }
+                        self.levels.truncate(1);
+                    }
+                },
+            }
+        }
+    }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+impl<'a, I> LayoutParse<'a, I> {
+    /// Queues `token`, preceded by any pseudo-tokens if it starts a line.
+    fn push(&mut self, token: Token<'a>) {
+        let starts_line = self.depth == 0 && self.end.map_or(true, |(_, position)| token.start.line > position.line);
+
+        if starts_line {
+            if let Some((index, position)) = self.end {
+                self.pending.push_back(Ok(pseudo(self.layout.newline, index, position)));
+            }
+
+            let width = self.indentation(token.span.start);
+            let at = |class| pseudo(class, token.span.start, token.start);
+
+            if width > self.current() {
+                self.levels.push(width);
+                self.pending.push_back(Ok(at(self.layout.indent)));
+            } else {
+                while width < self.current() {
+                    self.levels.pop();
+                    self.pending.push_back(Ok(at(self.layout.dedent)));
+                }
+
+                if width > self.current() {
+                    // carry on at the new width, rather than reporting every line
+                    self.levels.push(width);
+                    self.done = !self.recover;
+                    self.pending.push_back(Err(ParseError {
+                        span:       Span { start: token.span.start, end: token.span.start },
+                        start:      token.start,
+                        found:      token.lexeme.chars().next(),
+                        found_at:   token.start,
+                        expected:   vec![self.layout.dedent],
+                        unlexable:  Span { start: token.span.start, end: token.span.start },
+                        incomplete: false,
+                        reason:     Some("unindent does not match any outer indentation level".to_string()),
+                    }));
+
+                    if self.done {
+                        return;
+                    }
+                }
+            }
+        }
+
+        if self.layout.brackets.iter().any(|&(open, _)| open == token.class) {
+            self.depth += 1;
+        } else if self.layout.brackets.iter().any(|&(_, close)| close == token.class) {
+            self.depth = self.depth.saturating_sub(1);
+        }
+
+        self.end = Some((token.span.end, token.end(self.text)));
+
+        self.pending.push_back(Ok(token));
+    }
+
+    fn current(&self) -> usize {
+        self.levels.last().copied().unwrap_or(0)
+    }
+
+    /// Returns the width of the text between the start of the line and `index`.
+    fn indentation(&self, index: usize) -> usize {
+        let line = self.text[..index].rsplit(position::is_line_break).next().unwrap_or("");
+        line.chars().fold(0, |width, c| match c {
+            '\t' => (width / self.layout.tab_width + 1) * self.layout.tab_width,
+            _    => width + 1,
+        })
+    }
+}
+
+/// Returns an empty token of a pseudo-token class.
+fn pseudo<'a>(class: usize, index: usize, start: Position) -> Token<'a> {
+    Token { lexeme: "", class, span: Span { start: index, end: index }, start }
+}
\ No newline at end of file
@@ -1,31 +1,183 @@
 pub use self::compile::LexDef;
-pub use self::parse::{Token, Parse, ParseError};
+pub use self::analysis::Diagnostic;
+pub use self::parse::{Token, Parse};
+pub use self::error::ParseError;
+pub use self::position::{Span, Position};
+pub use self::units::{Unit, UnitToken, UnitParse};
+pub use self::stream::{OwnedToken, StreamError, StreamParse};
+pub use self::incremental::{Edit, TokenBuffer};
+pub use self::value::{Value, Escapes, Converter, ValueToken, Values};
+pub use self::layout::{Layout, LayoutParse};
+pub use self::trace::{Scan, TracedParse};
+
+use crate::lang::re::Encoding;
+use std::io::Read;
 
 #[derive(Debug, Clone)]
 pub enum Command {
     Emit,
     Skip,
+    /// Keeps the lexeme as the start of the next token's lexeme.
+    More,
+    /// Emits the token as the contained class.
+    Type(usize),
+    /// Reports the token as an error with the contained message.
+    Error(String),
+    /// Chooses the command to apply by inspecting the lexeme.
+    Callback(fn(&str) -> Command),
+}
+
+/// Change to the mode stack after matching a token. Modes are indices into
+/// `LexDef::modes`, and lexing starts with only the initial mode on the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeChange {
+    /// Enters a mode, returning to the current one on `Pop`.
+    Push(usize),
+    /// Returns to the mode before the last `Push`, if any.
+    Pop,
+    /// Replaces the current mode.
+    Switch(usize),
+}
+
+/// Delimiters of a token that nests, such as a block comment that may contain
+/// other block comments. The rule matches the open delimiter, and the token
+/// extends to the close delimiter that balances it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nested {
+    pub open:  String,
+    pub close: String,
+}
+
+/// Outcome of an external scanner, which ends tokens that no regex can
+/// express, such as raw strings with matching counts of hashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scanned {
+    /// The token ends after `len` bytes of the scanned text. The scanner read
+    /// `read` bytes to decide, so edits past them cannot change the token.
+    Token { len: usize, read: usize },
+    /// The token is malformed for `reason`, as decided by reading `read` bytes.
+    Error { reason: String, read: usize },
+    /// The scanned text ended before the scanner could decide, so it is given
+    /// more text, or the token is unterminated if the text was complete.
+    More,
 }
 
+/// Ends a token given some text from its start, the length of the prefix
+/// matched by its rule, and whether the text is complete, i.e. runs to the
+/// end of the input.
+pub type Scanner = fn(&str, usize, bool) -> Scanned;
+
 pub struct LexAnalyzer {
-    next:     Vec<usize>,
-    classes:  Vec<Option<usize>>,
-    commands: Vec<Command>,
+    next:         Vec<usize>,
+    classes:      Vec<Option<usize>>,
+    accel:        Vec<Option<Vec<u8>>>, // bytes that exit each accelerated state
+    starts:       Vec<usize>,           // start state of each mode
+    commands:     Vec<Command>,
+    mode_changes: Vec<Option<ModeChange>>,
+    converters:   Vec<Option<Converter>>,
+    nested:       Vec<Option<(Vec<u8>, Vec<u8>)>>, // encoded open and close delimiters of nested classes
+    scanners:     Vec<Option<Scanner>>,
+    layout:       Option<Layout>,
+    encoding:     Encoding,
 }
 
 impl LexAnalyzer {
+    /// # Panics
+    /// Panics if the lexer was not compiled for UTF-8 input.
     #[must_use]
     pub fn parse<'a>(&'a self, text: &'a str) -> Parse<'a> {
+        assert_eq!(self.encoding, Encoding::Utf8, "lexer expects {:?} input", self.encoding);
         Parse::new(&self, text)
     }
+
+    /// # Panics
+    /// Panics if the lexer was not compiled for Latin-1 input.
+    #[must_use]
+    pub fn parse_latin1<'a>(&'a self, text: &'a [u8]) -> UnitParse<'a, u8> {
+        assert_eq!(self.encoding, Encoding::Latin1, "lexer expects {:?} input", self.encoding);
+        UnitParse::new(self, text)
+    }
+
+    /// # Panics
+    /// Panics if the lexer was not compiled for UTF-16 input.
+    #[must_use]
+    pub fn parse_utf16<'a>(&'a self, text: &'a [u16]) -> UnitParse<'a, u16> {
+        assert_eq!(self.encoding, Encoding::Utf16, "lexer expects {:?} input", self.encoding);
+        UnitParse::new(self, text)
+    }
+
+    /// Lexes UTF-8 input pulled from `reader`, without holding all of it in memory.
+    ///
+    /// # Panics
+    /// Panics if the lexer was not compiled for UTF-8 input.
+    #[must_use]
+    pub fn parse_reader<R: Read>(&self, reader: R) -> StreamParse<'_, R> {
+        assert_eq!(self.encoding, Encoding::Utf8, "lexer expects {:?} input", self.encoding);
+        StreamParse::new(self, reader)
+    }
+
+    /// Lexes `text` in recovery mode, recording every step of the dfa along
+    /// with the longest match it settled on, for debugging lexer definitions.
+    ///
+    /// # Panics
+    /// Panics if the lexer was not compiled for UTF-8 input.
+    #[must_use]
+    pub fn parse_traced<'a>(&'a self, text: &'a str) -> TracedParse<'a> {
+        assert_eq!(self.encoding, Encoding::Utf8, "lexer expects {:?} input", self.encoding);
+        TracedParse::new(Parse::new(self, text).recover().traced())
+    }
+
+    /// Lexes `text` in recovery mode on up to `threads` threads, giving the
+    /// same items as `parse(text).recover()`. Large inputs are split into
+    /// chunks that are lexed speculatively, then checked to line up.
+    ///
+    /// # Panics
+    /// Panics if the lexer was not compiled for UTF-8 input.
+    #[must_use]
+    pub fn parse_parallel<'a>(&'a self, text: &'a str, threads: usize) -> Vec<Result<Token<'a>, ParseError>> {
+        assert_eq!(self.encoding, Encoding::Utf8, "lexer expects {:?} input", self.encoding);
+        parallel::lex(self, text, threads)
+    }
+
+    /// Lexes `text` in recovery mode, keeping the tokens so they can be updated
+    /// after edits without lexing all of the text again.
+    ///
+    /// # Panics
+    /// Panics if the lexer was not compiled for UTF-8 input.
+    #[must_use]
+    pub fn parse_incremental(&self, text: &str) -> TokenBuffer<'_> {
+        assert_eq!(self.encoding, Encoding::Utf8, "lexer expects {:?} input", self.encoding);
+        TokenBuffer::new(self, text)
+    }
+
+    #[must_use]
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Returns the classes of indentation pseudo-tokens, if the lexer has any.
+    #[must_use]
+    pub fn layout(&self) -> Option<&Layout> {
+        self.layout.as_ref()
+    }
 }
 
 // =================
 // === INTERNALS ===
 // =================
 
+mod analysis;
 mod compile;
+mod error;
+mod incremental;
+mod layout;
+mod parallel;
 mod parse;
+mod position;
+mod stream;
+mod trace;
+mod units;
+mod value;
 
 #[cfg(test)]
 mod tests;
\ No newline at end of file
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::thread;
+use super::{LexAnalyzer, Token, ParseError};
+use super::parse::{Parse, Checkpoint};
+use super::position::Cursor;
+
+/// Smallest chunk of input worth lexing on its own thread.
+const MIN_CHUNK_SIZE: usize = 32 * 1024;
+
+/// Lexes `text` in recovery mode on up to `threads` threads. The text is split
+/// after newlines, and each chunk is lexed speculatively from the initial mode.
+/// Chunks are then stitched together where a chunk lines up with the state
+/// the previous chunk ended in, and only relexed serially where it does not,
+/// e.g. when it started inside a multi-line string or comment.
+pub(super) fn lex<'a>(lex: &'a LexAnalyzer, text: &'a str, threads: usize) -> Vec<Result<Token<'a>, ParseError>> {
+    let chunks = split(text, threads);
+    if chunks.len() == 1 {
+        return Parse::new(lex, text).recover().collect();
+    }
+
+    // positions of chunk starts, from the line breaks within each chunk
+    let deltas = in_parallel(&chunks, |&(start, end)| {
+        let mut cursor = Cursor::default();
+        cursor.advance(&text[start..end]);
+        cursor
+    });
+    let mut cursors = vec![Cursor::default()];
+    for delta in &deltas[..deltas.len() - 1] {
+        cursors.push(cursors[cursors.len() - 1].then(*delta));
+    }
+
+    let froms: Vec<_> = chunks.iter().zip(cursors).map(|(&(start, end), cursor)| {
+        (Checkpoint { index: start, cursor, mode: 0, modes: Vec::new() }, end)
+    }).collect();
+    let runs = in_parallel(&froms, |(from, end)| Run::lex(lex, text, from.clone(), *end));
+
+    let mut items = Vec::new();
+    let mut state = froms[0].0.clone();
+
+    for (run, &(_, end)) in runs.into_iter().zip(&chunks) {
+        if state.index >= end {
+            continue; // the previous chunk's last token covered this chunk
+        }
+
+        // relex until lining up with the speculative run
+        let mut parse = Parse::resume(lex, text, state);
+        state = loop {
+            let from = parse.checkpoint();
+
+            if let Some(i) = run.find(&from) {
+                let mut run = run;
+                items.extend(run.items.drain(i..));
+                break run.exit;
+            } else if from.index >= end && end < text.len() {
+                break from;
+            }
+
+            match parse.next() {
+                Some(item) => items.push(item),
+                None => break parse.checkpoint(),
+            }
+        };
+    }
+
+    items
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+/// Items lexed from the start of a chunk until the first item starting past
+/// its end, with the state before each item. As chunks are lexed from their
+/// true positions, states at the same index only differ in their modes.
+struct Run<'a> {
+    items:  Vec<Result<Token<'a>, ParseError>>,
+    froms:  Vec<(usize, usize)>,        // index and mode before each item
+    stacks: HashMap<usize, Vec<usize>>, // modes to return to before each item, where there are any
+    exit:   Checkpoint,
+}
+
+impl<'a> Run<'a> {
+    fn lex(lex: &'a LexAnalyzer, text: &'a str, from: Checkpoint, end: usize) -> Self {
+        let mut parse = Parse::resume(lex, text, from);
+        let mut run = Self { items: Vec::new(), froms: Vec::new(), stacks: HashMap::new(), exit: parse.checkpoint() };
+
+        loop {
+            let from = parse.checkpoint();
+            if from.index >= end && end < text.len() {
+                run.exit = from;
+                return run;
+            }
+
+            let Some(item) = parse.next() else {
+                run.exit = parse.checkpoint();
+                return run;
+            };
+
+            if !from.modes.is_empty() {
+                run.stacks.insert(run.items.len(), from.modes);
+            }
+            run.froms.push((from.index, from.mode));
+            run.items.push(item);
+        }
+    }
+
+    /// Returns the index of the item lexed from `from`, if any.
+    fn find(&self, from: &Checkpoint) -> Option<usize> {
+        let i = self.froms.partition_point(|&(index, _)| index < from.index);
+        let modes = self.stacks.get(&i).map_or(&[][..], Vec::as_slice);
+        self.froms.get(i).filter(|&&(index, mode)| index == from.index && mode == from.mode && modes == from.modes.as_slice()).map(|_| i)
+    }
+}
+
+/// Splits `text` into at most `threads` chunks of at least `MIN_CHUNK_SIZE`
+/// bytes, each ending after a newline (or at the end of the text).
+fn split(text: &str, threads: usize) -> Vec<(usize, usize)> {
+    let count = threads.min(text.len() / MIN_CHUNK_SIZE).max(1);
+    let mut chunks = Vec::with_capacity(count);
+    let mut start = 0;
+
+    for i in 1..=count {
+        let end = if i == count {
+            text.len()
+        } else {
+            let target = (i * text.len() / count).max(start);
+            text.as_bytes()[target..].iter().position(|&byte| byte == b'\n').map_or(text.len(), |offset| target + offset + 1)
+        };
+
+        if end > start || i == count {
+            chunks.push((start, end));
+            start = end;
+        }
+    }
+
+    chunks
+}
+
+/// Maps `items` with `f` on a thread per item.
+fn in_parallel<T: Sync, U: Send>(items: &[T], f: impl Fn(&T) -> U + Sync) -> Vec<U> {
+    thread::scope(|scope| {
+        let f = &f;
+        let handles: Vec<_> = items.iter().map(|item| scope.spawn(move || f(item))).collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
\ No newline at end of file
@@ -1,20 +1,35 @@
-use super::{LexAnalyzer, Command};
+use super::{LexAnalyzer, Command, ModeChange, Scanned, Scanner, Unit, Span, Position, ParseError, Values, LayoutParse, Scan};
+use super::position::Cursor;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Units past the prefix of a token first given to its external scanner.
+const SCAN_WINDOW: usize = 256;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Token<'a> {
     pub lexeme: &'a str,
     pub class:  usize,
+    pub span:   Span,
+    pub start:  Position, // position of first char of lexeme
 }
 
-pub struct Parse<'a> {
-    lex:   &'a LexAnalyzer,
-    text:  &'a str,
-    index: usize,
+impl Token<'_> {
+    /// Returns the position just past the lexeme, within the `text` it was
+    /// lexed from.
+    #[must_use]
+    pub fn end(&self, text: &str) -> Position {
+        let mut cursor = Cursor::resume(text, self.span.start, self.start);
+        cursor.advance(self.lexeme);
+        cursor.position
+    }
 }
 
-#[derive(Debug)]
-pub struct ParseError {
-    pos: usize,
+pub struct Parse<'a> {
+    lex:    &'a LexAnalyzer,
+    text:   &'a str,
+    lexing: Lexing,
+    trivia: bool, // whether the latest item was a skipped token
 }
 
 impl<'a> Parse<'a> {
@@ -22,78 +37,610 @@ impl<'a> Parse<'a> {
         Self {
             lex,
             text,
-            index: 0,
+            lexing: Lexing::default(),
+            trivia: false,
         }
     }
+
+    /// Resumes lexing `text` in recovery mode from a checkpoint.
+    pub(super) fn resume(lex: &'a LexAnalyzer, text: &'a str, checkpoint: Checkpoint) -> Self {
+        let mut parse = Self::new(lex, text);
+        parse.lexing.index = checkpoint.index;
+        parse.lexing.cursor = checkpoint.cursor;
+        parse.lexing.recover = true;
+        parse.lexing.mode = checkpoint.mode;
+        parse.lexing.modes = checkpoint.modes;
+        parse
+    }
+
+    /// Returns the state between items to resume lexing from.
+    pub(super) fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            index: self.lexing.index,
+            cursor: self.lexing.cursor,
+            mode: self.lexing.mode,
+            modes: self.lexing.modes.clone(),
+        }
+    }
+
+    /// Returns the index past all text read while lexing the latest item,
+    /// including skipped tokens before it, or past the end of the text if it
+    /// was reached.
+    pub(super) fn extent(&self) -> usize {
+        self.lexing.extent
+    }
+
+    /// Starts recording each run of the dfa.
+    pub(super) fn traced(mut self) -> Self {
+        self.lexing.scans = Some(Vec::new());
+        self
+    }
+
+    /// Returns the dfa runs recorded since the last call.
+    pub(super) fn take_scans(&mut self) -> Vec<Scan> {
+        self.lexing.scans.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Returns the current lexer mode.
+    #[must_use]
+    pub fn mode(&self) -> usize {
+        self.lexing.mode
+    }
+
+    /// Switches to recovery mode, where lexing continues after an error by
+    /// skipping the error's `unlexable` span. Each error then stands in for
+    /// a token covering that span, so the yielded items cover all the input
+    /// besides skipped tokens.
+    #[must_use]
+    pub fn recover(mut self) -> Self {
+        self.lexing.recover = true;
+        self
+    }
+
+    /// Also yields the tokens of skipped classes, such as whitespace and
+    /// comments, as when highlighting all of the text.
+    #[must_use]
+    pub fn skipped(mut self) -> Self {
+        self.lexing.skipped = true;
+        self
+    }
+
+    /// Checks if the latest item was a skipped token.
+    pub(crate) fn was_skipped(&self) -> bool {
+        self.trivia
+    }
+
+    /// Pairs tokens with the values of their lexemes, as converted by the
+    /// converter of their class. Conversion errors are reported as lexical
+    /// errors, so call after `recover` to keep lexing past them.
+    #[must_use]
+    pub fn values(self) -> Values<'a> {
+        let recover = self.lexing.recover;
+        Values::new(self.lex, self.text, self, recover)
+    }
+
+    /// Adds the indentation pseudo-tokens of the lexer's layout. Inconsistent
+    /// dedents are reported as lexical errors.
+    ///
+    /// # Panics
+    /// Panics if the lexer has no layout.
+    #[must_use]
+    pub fn layout(self) -> LayoutParse<'a> {
+        let recover = self.lexing.recover;
+        LayoutParse::new(self.lex, self.text, self, recover)
+    }
 }
 
 impl<'a> Iterator for Parse<'a> {
     type Item = Result<Token<'a>, ParseError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while self.index < self.text.len() {
-            let mut state = 0;
-            let mut index = self.index;
-            
-            let mut last_accept_state = self.lex.sink();
-            let mut last_accept_index = 0_usize;
-
-            // simulate dfa until hit the sink state or end of text
-            for byte in self.text[self.index..].bytes() {            
-                if state == self.lex.sink() {
+        self.trivia = false;
+
+        match self.lexing.next(self.lex, self.text.as_bytes(), 0, true)? {
+            Lexed::Token { class, start, end, position, skipped } => {
+                self.trivia = skipped;
+                Some(Ok(Token { lexeme: &self.text[start..end], class, span: Span { start, end }, start: position }))
+            },
+            Lexed::Error(error) => Some(Err(error)),
+            Lexed::Starved => unreachable!(),
+        }
+    }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+/// Lexing state shared by `Parse`, `UnitParse` and `StreamParse`, which
+/// turn the items it lexes from their units into their own items.
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub(super) struct Lexing {
+    pub index:   usize, // unit index of the next token
+    pub cursor:  Cursor,
+    pub recover: bool,
+    pub skipped: bool, // whether to yield skipped tokens too
+    pub done:    bool,
+    pub mode:    usize,
+    pub modes:   Vec<usize>,                      // modes to return to on pop
+    pub more:    Option<(usize, Position, usize)>, // unit index, position and first class of a token continued by `More`
+    pub cut_off: bool, // whether the input ended part way through a longer token than some match
+    pub memo:    Memo,
+    pub extent:  usize, // unit index past the units read to lex the latest item
+    pub scans:   Option<Vec<Scan>>, // dfa runs not yet taken, when tracing
+}
+
+/// Item lexed by `Lexing`, with tokens spanning unit indices.
+pub(super) enum Lexed {
+    /// Token of `class` over units `start..end`, which is `skipped` if its
+    /// class is.
+    Token { class: usize, start: usize, end: usize, position: Position, skipped: bool },
+    Error(ParseError),
+    /// The units ran out before the input did, so lexing can only go on once
+    /// more units are added.
+    Starved,
+}
+
+impl Lexing {
+    /// Lexes the next item from `units`, which start at input index `offset`
+    /// and are followed by more input unless `at_end`.
+    pub fn next<U: Unit>(&mut self, lex: &LexAnalyzer, units: &[U], offset: usize, at_end: bool) -> Option<Lexed> {
+        self.extent = 0;
+
+        while !self.done {
+            if self.index == units.len() {
+                if at_end {
                     break;
                 }
-                
-                if self.lex.classes[state].is_some() {
-                    last_accept_state = state;
-                    last_accept_index = index;
-                }
-                
-                state = self.lex.step(state, byte);
-                index += 1;
+                return Some(Lexed::Starved);
             }
 
-            // currently on an accept state
-            if let Some(class) = self.lex.classes[state] {
-                let i = self.index;
-                self.index = index;
+            match self.longest_match(lex, units, at_end) {
+                // the token may continue past the end of the units, or the
+                // error may need the rest of a char at the end of them
+                Err(failure) if !at_end && failure.reached + 4 > units.len() => return Some(Lexed::Starved),
+                Ok(Match { class, end, extent, unterminated, cut_off, reason }) => {
+                    self.extent = self.extent.max(extent);
+                    self.cut_off |= cut_off;
 
-                match self.lex.commands[class] {
-                    Command::Emit => return Some(Ok(Token { lexeme: &self.text[i..self.index], class })),
-                    Command::Skip => (),
-                };
-            // landed on an accept state in the past
-            } else if let Some(class) = self.lex.classes[last_accept_state] {
-                let i = self.index;
-                self.index = last_accept_index;
-
-                match self.lex.commands[class] {
-                    Command::Emit => return Some(Ok(Token { lexeme: &self.text[i..self.index], class })), 
-                    Command::Skip => (),
-                };
-            // failed to match anything
-            } else {
-                let i = self.index;
-                self.index = usize::MAX; // forces next iteration to return None
+                    let i = self.index;
+                    self.index = end;
+
+                    let start = self.cursor.position;
+                    self.cursor.advance(&U::decode(&units[i..end], lex.encoding));
+                    lex.change_mode(&mut self.mode, &mut self.modes, class);
+
+                    // tokens continued by `More` extend back to their first match
+                    let (i, start, first) = self.more.take().unwrap_or((i, start, class));
+                    let span = Span { start: offset + i, end: offset + end };
+
+                    if unterminated || reason.is_some() {
+                        self.done = unterminated || !self.recover;
+                        return Some(Lexed::Error(self.token_error(span, start, class, reason)));
+                    }
+
+                    let command = match &lex.commands[class] {
+                        Command::Callback(_) => lex.commands[class].resolve(&U::decode(&units[i..end], lex.encoding)),
+                        command => Cow::Borrowed(command),
+                    };
+
+                    match command.as_ref() {
+                        Command::Emit => return Some(Lexed::Token { class, start: i, end, position: start, skipped: false }),
+                        Command::Type(class) => return Some(Lexed::Token { class: *class, start: i, end, position: start, skipped: false }),
+                        Command::Skip if self.skipped => return Some(Lexed::Token { class, start: i, end, position: start, skipped: true }),
+                        Command::Skip => (),
+                        Command::More => self.more = Some((i, start, first)),
+                        Command::Error(reason) => {
+                            self.done = !self.recover;
+                            return Some(Lexed::Error(self.token_error(span, start, class, Some(reason.clone()))));
+                        },
+                        Command::Callback(_) => unreachable!(),
+                    }
+                },
+                // failed to match anything
+                Err(failure) => return Some(Lexed::Error(self.failed(lex, units, offset, failure))),
+            }
+        }
+
+        // input ended before the token continued by `More` did
+        self.done = true;
+        let (i, start, class) = self.more.take()?;
+        Some(Lexed::Error(self.token_error(Span { start: offset + i, end: offset + units.len() }, start, class, None)))
+    }
+
+    /// Finds the longest match at the current index, recording the run of the
+    /// dfa when tracing.
+    fn longest_match<U: Unit>(&mut self, lex: &LexAnalyzer, units: &[U], at_end: bool) -> Result<Match, Failure> {
+        match &mut self.scans {
+            Some(scans) => {
+                let (found, scan) = lex.traced_match(self.mode, units, self.index, at_end, &mut self.memo);
+                scans.push(scan);
+                found
+            },
+            None => lex.longest_match(self.mode, units, self.index, at_end, &mut self.memo, &mut ()),
+        }
+    }
+
+    /// Reports a token of `class` that ended up an error, for being rejected
+    /// or unterminated, up to the current position. The whole token is
+    /// unlexable, as recovery goes on after it.
+    fn token_error(&self, span: Span, start: Position, class: usize, reason: Option<String>) -> ParseError {
+        ParseError {
+            span,
+            start,
+            found:      None,
+            found_at:   self.cursor.position,
+            expected:   vec![class],
+            unlexable:  span,
+            incomplete: self.cut_off || reason.is_none(),
+            reason,
+        }
+    }
 
-                return Some(Err(ParseError { pos: i }));
+    /// Reports the failure to match any token at the current index, and in
+    /// recovery mode skips one char, the smallest span that guarantees
+    /// progress.
+    fn failed<U: Unit>(&mut self, lex: &LexAnalyzer, units: &[U], offset: usize, failure: Failure) -> ParseError {
+        self.extent = self.extent.max(failure.reached + 1);
+
+        let i = self.index;
+        self.more = None;
+
+        // the dfa may die part way through a multi-byte char
+        let mut reached = i;
+        while reached < failure.reached {
+            let len = U::char_len(&units[reached..], lex.encoding);
+            if reached + len > failure.reached {
+                break;
             }
+            reached += len;
+        }
+
+        let mut cursor = self.cursor;
+        cursor.advance(&U::decode(&units[i..reached], lex.encoding));
+
+        let found = &units[reached..units.len().min(reached + 4)];
+        // recovery skips a single char, as the next one may start a token
+        let unlexable = U::char_len(&units[i..], lex.encoding);
+
+        let found = U::decode(found, lex.encoding).chars().next();
+
+        let error = ParseError {
+            span:       Span { start: offset + i, end: offset + reached },
+            start:      self.cursor.position,
+            found,
+            found_at:   cursor.position,
+            expected:   lex.viable_classes(failure.state),
+            unlexable:  Span { start: offset + i, end: offset + i + unlexable },
+            incomplete: self.cut_off || found.is_none(),
+            reason:     None,
         };
-        
-        None
+
+        if self.recover {
+            self.index += unlexable;
+            self.cursor.advance(&U::decode(&units[i..self.index], lex.encoding));
+        } else {
+            self.done = true;
+        }
+
+        error
     }
 }
 
-// =================
-// === INTERNALS ===
-// =================
+impl Command {
+    /// Resolves callbacks into the command to apply to `lexeme`. Callbacks
+    /// may return further callbacks, which are resolved in turn.
+    pub(super) fn resolve(&self, lexeme: &str) -> Cow<'_, Command> {
+        match self {
+            Command::Callback(callback) => Cow::Owned(callback(lexeme).resolve(lexeme).into_owned()),
+            command => Cow::Borrowed(command),
+        }
+    }
+}
+
+/// State of a `Parse` between items, from which lexing can resume.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(super) struct Checkpoint {
+    pub index:  usize,
+    pub cursor: Cursor,
+    pub mode:   usize,
+    pub modes:  Vec<usize>,
+}
+
+/// Longest match found by the lexer dfa.
+pub(super) struct Match {
+    pub class: usize,
+    pub end:   usize,
+    /// Index past the last unit read, which could change the match if edited,
+    /// or past `units.len()` if the end of input was reached.
+    pub extent: usize,
+    /// Whether the input ended inside a nested token, which then ends there.
+    pub unterminated: bool,
+    /// Whether the input ended while the dfa could still reach a longer
+    /// match, which more input might complete.
+    pub cut_off: bool,
+    /// Why an external scanner rejected the token, which then ends after
+    /// the prefix matched by the dfa.
+    pub reason: Option<String>,
+}
+
+/// Describes where the lexer dfa failed to match any token.
+#[derive(Clone, Copy)]
+pub(super) struct Failure {
+    /// Index of the unit that led to the sink state, or end of input.
+    pub reached: usize,
+    /// Last state before reaching the sink state or end of input.
+    pub state: usize,
+}
+
+/// Watches the dfa of `longest_match`, as when tracing.
+pub(super) trait Observer {
+    /// The dfa is in `state`, accepting `class`, at the start of the scan or
+    /// after reading another unit.
+    fn state(&mut self, _state: usize, _class: Option<usize>) {}
+    /// The dfa stopped at a pair of state and unit index memoized as failing.
+    fn memoized(&mut self) {}
+    /// A nested or external rule extended the match to end at `end`.
+    fn extended(&mut self, _end: usize) {}
+}
+
+impl Observer for () {}
+
+/// Pairs of state and unit index that earlier scans went past their last
+/// accept state from, with the failure they lead to. Scans stop on reaching
+/// a memoized pair, so no unit is scanned twice in the same state, making
+/// maximal munch linear time (as in Reps' algorithm).
+#[derive(Default)]
+pub(super) struct Memo {
+    failed:  HashMap<(usize, usize), Failure>,
+    horizon: usize, // index past the furthest memoized pair
+}
+
+impl Memo {
+    /// Forgets all pairs, e.g. when unit indices change.
+    pub fn clear(&mut self) {
+        self.failed.clear();
+        self.horizon = 0;
+    }
+}
 
 impl LexAnalyzer {
-    fn sink(&self) -> usize { 
+    /// Simulates the dfa of `mode` from `start` until hitting the sink state or
+    /// the end of `units`, returning the class and end index of the longest match.
+    /// Matches of nested classes extend to their balancing close delimiter, and
+    /// external scanners take over from matches of their classes.
+    /// If `units` is not `at_end` of the input, running out of units before the
+    /// sink state (or the close delimiter) fails, reaching `units.len()`.
+    pub(super) fn longest_match<U: Unit, O: Observer>(&self, mode: usize, units: &[U], start: usize, at_end: bool, memo: &mut Memo, observer: &mut O) -> Result<Match, Failure> {
+        let found = self.dfa_match(mode, units, start, at_end, memo, observer)?;
+
+        if let Some(scanner) = self.scanners[found.class] {
+            let found = self.external_match(scanner, found, units, start, at_end);
+            if let Ok(found) = &found {
+                observer.extended(found.end);
+            }
+            return found;
+        }
+
+        let Some((open, close)) = &self.nested[found.class] else {
+            return Ok(found);
+        };
+        let (open_len, close_len) = (open.len() / std::mem::size_of::<U>(), close.len() / std::mem::size_of::<U>());
+
+        // the dfa matched the open delimiter
+        let mut depth = 1;
+        let mut index = found.end;
+
+        while index < units.len() {
+            if U::starts_with(&units[index..], close) {
+                index += close_len;
+                depth -= 1;
+                if depth == 0 {
+                    observer.extended(index);
+                    let extent = found.extent.max(index + open_len.max(close_len)).min(units.len() + 1);
+                    return Ok(Match { end: index, extent, ..found });
+                }
+            } else if U::starts_with(&units[index..], open) {
+                index += open_len;
+                depth += 1;
+            } else {
+                index += 1;
+            }
+        }
+
+        if at_end {
+            observer.extended(units.len());
+            Ok(Match { end: units.len(), extent: units.len() + 1, unterminated: true, ..found })
+        } else {
+            Err(Failure { reached: units.len(), state: self.sink() })
+        }
+    }
+
+    /// Hands the text from `start` to an external scanner, along with the length
+    /// of the prefix matched by the dfa. The text is a window past the prefix,
+    /// which doubles each time the scanner needs more, so scanning stays linear.
+    /// Unless `at_end`, running out of units fails, reaching `units.len()`.
+    fn external_match<U: Unit>(&self, scanner: Scanner, found: Match, units: &[U], start: usize, at_end: bool) -> Result<Match, Failure> {
+        let prefix_len = U::decode_valid(&units[start..found.end], self.encoding).len();
+        let mut window = SCAN_WINDOW;
+
+        loop {
+            let end = found.end.saturating_add(window).min(units.len());
+            let complete = at_end && end == units.len();
+            let text = U::decode_valid(&units[start..end], self.encoding);
+
+            // index past the units read by the scanner, or past the end of
+            // input if the scanner read to it
+            let extent = |read: usize| {
+                let mut read = read.min(text.len());
+                while !text.is_char_boundary(read) {
+                    read += 1;
+                }
+
+                let extent = if complete && read == text.len() { units.len() + 1 } else { start + U::count(&text[..read], self.encoding) };
+                extent.max(found.extent)
+            };
+
+            match scanner(&text, prefix_len, complete) {
+                Scanned::Token { len, .. } if len > text.len() || !text.is_char_boundary(len) => {
+                    let reason = format!("scanner ended the token at {len}, which is not a char boundary of the scanned text");
+                    return Ok(Match { extent: extent(text.len()), reason: Some(reason), ..found });
+                },
+                Scanned::Token { len, read } => {
+                    let len = len.max(prefix_len);
+                    let end = start + U::count(&text[..len], self.encoding);
+                    return Ok(Match { end, extent: extent(read.max(len)), ..found });
+                },
+                Scanned::Error { reason, read } => return Ok(Match { extent: extent(read), reason: Some(reason), ..found }),
+                Scanned::More if end < units.len() => window = window.saturating_mul(2),
+                Scanned::More if at_end => return Ok(Match { end: units.len(), extent: units.len() + 1, unterminated: true, ..found }),
+                Scanned::More => return Err(Failure { reached: units.len(), state: self.sink() }),
+            }
+        }
+    }
+
+    /// Runs the dfa alone, as in `longest_match`.
+    fn dfa_match<U: Unit, O: Observer>(&self, mode: usize, units: &[U], start: usize, at_end: bool, memo: &mut Memo, observer: &mut O) -> Result<Match, Failure> {
+        // later scans start past all memoized pairs
+        if start >= memo.horizon && !memo.failed.is_empty() {
+            memo.clear();
+        }
+
+        let mut state = self.starts[mode];
+        let mut prev_state = state;
+        let mut index = start;
+        let mut known = None; // failure found by an earlier scan
+        
+        let mut last_accept_state = self.sink();
+        let mut last_accept_index = 0_usize;
+        observer.state(state, self.classes[state]);
+
+        while index < units.len() && state != self.sink() {
+            if self.classes[state].is_some() {
+                last_accept_state = state;
+                last_accept_index = index;
+            }
+
+            // skip ahead to the next unit that leaves an accelerated state
+            if let Some(exits) = &self.accel[state] {
+                let offset = units[index..].iter().position(|unit| unit.exits(exits));
+                for _ in index..offset.map_or(units.len(), |offset| index + offset) {
+                    observer.state(state, self.classes[state]);
+                }
+
+                if let Some(offset) = offset {
+                    index += offset;
+                } else {
+                    index = units.len();
+                    break;
+                }
+
+                if self.classes[state].is_some() {
+                    last_accept_index = index;
+                }
+            }
+
+            if !memo.failed.is_empty() {
+                if let Some(&failure) = memo.failed.get(&(state, index)) {
+                    observer.memoized();
+                    known = Some(failure);
+                    break;
+                }
+            }
+
+            prev_state = state;
+            state = units[index].step(self, state);
+            index += 1;
+            observer.state(state, self.classes[state]);
+        }
+
+        if known.is_none() && index == units.len() && state != self.sink() {
+            if !at_end {
+                // more input could extend the match
+                return Err(Failure { reached: index, state });
+            } else if let Some(class) = self.classes[state] {
+                // currently on an accept state
+                return Ok(Match { class, end: index, extent: index + 1, unterminated: false, cut_off: false, reason: None });
+            }
+        }
+
+        let failure = known.unwrap_or(if state == self.sink() {
+            Failure { reached: index - 1, state: prev_state }
+        } else {
+            Failure { reached: index, state }
+        });
+
+        // replay the units scanned past the last accept state (or the whole
+        // scan if nothing was accepted) to memoize that they lead nowhere
+        let (mut replay_state, mut replay_index) = if self.classes[last_accept_state].is_some() {
+            (last_accept_state, last_accept_index)
+        } else {
+            (self.starts[mode], start)
+        };
+        while replay_index < index {
+            replay_state = units[replay_index].step(self, replay_state);
+            replay_index += 1;
+            if replay_state == self.sink() {
+                break;
+            }
+            memo.failed.insert((replay_state, replay_index), failure);
+        }
+        memo.horizon = memo.horizon.max(index);
+
+        if let Some(class) = self.classes[last_accept_state] {
+            // landed on an accept state in the past
+            // the dfa only runs to the end of input while it is live
+            let cut_off = at_end && failure.reached == units.len();
+            Ok(Match { class, end: last_accept_index, extent: failure.reached + 1, unterminated: false, cut_off, reason: None })
+        } else {
+            Err(failure)
+        }
+    }
+
+    /// Applies the mode change on matching `class` to the current mode and
+    /// the stack of modes to return to.
+    pub(super) fn change_mode(&self, mode: &mut usize, modes: &mut Vec<usize>, class: usize) {
+        match self.mode_changes[class] {
+            Some(ModeChange::Push(next)) => modes.push(std::mem::replace(mode, next)),
+            Some(ModeChange::Pop) => *mode = modes.pop().unwrap_or(*mode),
+            Some(ModeChange::Switch(next)) => *mode = next,
+            None => (),
+        }
+    }
+
+    /// Returns the classes of all accept states reachable from `state`.
+    pub(super) fn viable_classes(&self, state: usize) -> Vec<usize> {
+        let mut visited = vec![false; self.classes.len()];
+        let mut stack = vec![state];
+        let mut classes = Vec::new();
+
+        visited[self.sink()] = true;
+        visited[state] = true;
+
+        while let Some(state) = stack.pop() {
+            if let Some(class) = self.classes[state] {
+                classes.push(class);
+            }
+            for symbol in 0..=u8::MAX {
+                let next_state = self.step(state, symbol);
+                if !visited[next_state] {
+                    visited[next_state] = true;
+                    stack.push(next_state);
+                }
+            }
+        }
+
+        classes.sort_unstable();
+        classes.dedup();
+        classes
+    }
+
+    pub(super) fn sink(&self) -> usize { 
         self.classes.len() - 1
     }
 
-    fn step(&self, id: usize, symbol: u8) -> usize {
+    pub(super) fn step(&self, id: usize, symbol: u8) -> usize {
         self.next[256 * id + symbol as usize]
     }
 }
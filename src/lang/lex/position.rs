@@ -0,0 +1,83 @@
+/// Byte range `start..end` of some text within the input.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub start: usize,
+    pub end:   usize,
+}
+
+/// Line and column of some char within the input. Both are 1-based, and
+/// columns are counted in chars.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Position {
+    pub line:   usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self { line: 1, column: 1 }
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+/// Tracks the position following all text fed to it so far. Line breaks are
+/// `\n`, `\r\n`, `\r` and the unicode separators NEL, LS and PS.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub(crate) struct Cursor {
+    pub position: Position,
+    after_cr: bool, // a `\n` directly after `\r` does not start another line
+}
+
+impl Cursor {
+    /// Returns the cursor at `position`, the position of `index` in `text`.
+    /// A `\n` at `index` then continues a line break started by a `\r`
+    /// before it.
+    pub fn resume(text: &str, index: usize, position: Position) -> Self {
+        Self { position, after_cr: text[..index].ends_with('\r') }
+    }
+
+    pub fn advance(&mut self, text: &str) {
+        for c in text.chars() {
+            if c == '\n' && self.after_cr {
+                self.after_cr = false;
+                continue;
+            }
+
+            self.after_cr = c == '\r';
+
+            if is_line_break(c) {
+                self.position.line += 1;
+                self.position.column = 1;
+            } else {
+                self.position.column += 1;
+            }
+        }
+    }
+
+    /// Returns the cursor after the text fed to this cursor followed by the
+    /// text fed to `other` from the start, which must not begin with a `\n`
+    /// that follows a `\r` fed to this cursor.
+    pub fn then(self, other: Self) -> Self {
+        if other.position == Position::default() {
+            self
+        } else if other.position.line > 1 {
+            Self { position: Position { line: self.position.line + other.position.line - 1, column: other.position.column }, ..other }
+        } else {
+            Self { position: Position { line: self.position.line, column: self.position.column + other.position.column - 1 }, ..other }
+        }
+    }
+}
+
+/// Checks if `c` breaks lines, as counted by `Cursor`.
+pub(crate) fn is_line_break(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{85}' | '\u{2028}' | '\u{2029}')
+}
\ No newline at end of file
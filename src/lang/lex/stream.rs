@@ -0,0 +1,144 @@
+use std::fmt;
+use std::io::{self, Read};
+use super::{LexAnalyzer, ParseError, Span, Position};
+use super::parse::{Lexing, Lexed};
+
+/// Minimum number of bytes read from the reader at a time.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Token that owns its lexeme, as produced by lexing a reader. Invalid UTF-8
+/// in lexemes is replaced.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OwnedToken {
+    pub lexeme: String,
+    pub class:  usize,
+    pub span:   Span,
+    pub start:  Position, // position of first char of lexeme
+}
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    Lex(ParseError),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(error) => error.fmt(f),
+            StreamError::Lex(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StreamError::Io(error) => Some(error),
+            StreamError::Lex(error) => Some(error),
+        }
+    }
+}
+
+/// Lexes input pulled from a reader. Only the current token and a chunk of
+/// lookahead are buffered, so memory is bounded by the longest token.
+pub struct StreamParse<'a, R> {
+    lex:    &'a LexAnalyzer,
+    reader: R,
+    buffer: Vec<u8>,
+    offset: usize, // input offset of the start of the buffer
+    eof:    bool,
+    lexing: Lexing, // over the buffer
+}
+
+impl<'a, R: Read> StreamParse<'a, R> {
+    pub(crate) fn new(lex: &'a LexAnalyzer, reader: R) -> Self {
+        Self {
+            lex,
+            reader,
+            buffer: Vec::new(),
+            offset: 0,
+            eof: false,
+            lexing: Lexing::default(),
+        }
+    }
+
+    /// Switches to recovery mode, as with `Parse::recover`.
+    #[must_use]
+    pub fn recover(mut self) -> Self {
+        self.lexing.recover = true;
+        self
+    }
+
+    /// Returns the current lexer mode.
+    #[must_use]
+    pub fn mode(&self) -> usize {
+        self.lexing.mode
+    }
+}
+
+impl<R: Read> Iterator for StreamParse<'_, R> {
+    type Item = Result<OwnedToken, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.lexing.next(self.lex, &self.buffer, self.offset, self.eof)? {
+                Lexed::Token { class, start, end, position, .. } => {
+                    let lexeme = String::from_utf8_lossy(&self.buffer[start..end]).into_owned();
+                    let span = Span { start: self.offset + start, end: self.offset + end };
+                    return Some(Ok(OwnedToken { lexeme, class, span, start: position }));
+                },
+                Lexed::Error(error) => return Some(Err(StreamError::Lex(error))),
+                Lexed::Starved => {
+                    if let Err(error) = self.fill() {
+                        self.lexing.done = true;
+                        return Some(Err(StreamError::Io(error)));
+                    }
+                },
+            }
+        }
+    }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+impl<R: Read> StreamParse<'_, R> {
+    /// Discards lexed input, keeping any partial token, then reads at least a
+    /// chunk, or as much as the buffer holds so long tokens are rescanned
+    /// only a logarithmic number of times.
+    fn fill(&mut self) -> io::Result<()> {
+        let keep = self.lexing.more.map_or(self.lexing.index, |(i, ..)| i);
+        self.buffer.drain(..keep);
+        self.lexing.memo.clear(); // memoized indices are into the buffer
+        self.offset += keep;
+        self.lexing.index -= keep;
+        if let Some((i, ..)) = &mut self.lexing.more {
+            *i -= keep;
+        }
+
+        let len = self.buffer.len();
+        let target = len + CHUNK_SIZE.max(len);
+        self.buffer.resize(target, 0);
+
+        let mut filled = len;
+        while filled < target {
+            match self.reader.read(&mut self.buffer[filled..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                },
+                Ok(n) => filled += n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+                Err(error) => {
+                    self.buffer.truncate(filled);
+                    return Err(error);
+                },
+            }
+        }
+
+        self.buffer.truncate(filled);
+        Ok(())
+    }
+}
\ No newline at end of file
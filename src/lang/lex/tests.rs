@@ -1,5 +1,10 @@
-use crate::lang::re;
+use crate::lang::re::{self, Encoding, RegEx};
 use super::parse::Token;
+use super::position::{Span, Position};
+use super::units::UnitToken;
+use super::{Command, Diagnostic, StreamError, Edit, Value, Escapes, Layout, Scan, Scanned};
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 // std::fs::write("_graph.dot", nfa.dot()).unwrap();
 
@@ -12,11 +17,806 @@ fn simple_lexer() {
             
     let tokens = lexer.parse("Waltz, bad nymph, for quick jigs vex").collect::<Result<Vec<_>, _>>().unwrap();
     
-    assert_eq!(tokens[0], Token { lexeme: "Waltz", class: 1 });
-    assert_eq!(tokens[1], Token { lexeme: "bad",   class: 1 });
-    assert_eq!(tokens[2], Token { lexeme: "nymph", class: 1 });
-    assert_eq!(tokens[3], Token { lexeme: "for",   class: 1 });
-    assert_eq!(tokens[4], Token { lexeme: "quick", class: 1 });
-    assert_eq!(tokens[5], Token { lexeme: "jigs",  class: 1 });
-    assert_eq!(tokens[6], Token { lexeme: "vex",   class: 1 });
+    assert_eq!((tokens[0].lexeme, tokens[0].class), ("Waltz", 1));
+    assert_eq!((tokens[1].lexeme, tokens[1].class), ("bad",   1));
+    assert_eq!((tokens[2].lexeme, tokens[2].class), ("nymph", 1));
+    assert_eq!((tokens[3].lexeme, tokens[3].class), ("for",   1));
+    assert_eq!((tokens[4].lexeme, tokens[4].class), ("quick", 1));
+    assert_eq!((tokens[5].lexeme, tokens[5].class), ("jigs",  1));
+    assert_eq!((tokens[6].lexeme, tokens[6].class), ("vex",   1));
+}
+
+#[test]
+fn accelerated_states() {
+    let lex_def = lex_def! {
+        [skip] _ws:      re::literal(" ").or(&re::literal("\n")).or(&re::literal("\t")).plus(),
+        [skip] _comment: re::literal("//").then(&re::literal("\n").not().star()),
+        string:          re::literal("\"").then(&re::literal("\"").not().star()).then(&re::literal("\"")),
+        ident:           re::range('a', 'z').plus(),
+        equals:          re::literal("="),
+        semicolon:       re::literal(";"),
+    }.1;
+
+    let lexer = lex_def.compile();
+    let mut baseline = lex_def.compile();
+    baseline.accel = vec![None; baseline.accel.len()];
+
+    assert!(lexer.accel.iter().any(Option::is_some));
+
+    let chunk = "let x = \"a long string literal that keeps going and going\";\n\
+                 // a long comment that the lexer should skip over in bulk\n\
+                 \t\t        spaces;\n";
+    let text = chunk.repeat(2000);
+
+    let tokens = lexer.parse(&text).collect::<Result<Vec<_>, _>>().unwrap();
+    let expected = baseline.parse(&text).collect::<Result<Vec<_>, _>>().unwrap();
+
+    assert_eq!(tokens.len(), 2000 * 7);
+    assert_eq!(tokens, expected);
+}
+
+#[test]
+fn encoded_input() {
+    let lex_def = lex_def! {
+        [skip] _ws: re::literal(" ").plus(),
+        word:       re::range('a', 'z').or(&re::range('\u{E0}', '\u{FF}')).or(&re::range('\u{1F600}', '\u{1F64F}')).plus(),
+        period:     re::literal("."),
+    }.1;
+
+    let text = "d\u{E9}j\u{E0} vu.";
+
+    let latin1 = text.chars().map(|c| c as u8).collect::<Vec<_>>();
+    let lexer = lex_def.compile_with(Encoding::Latin1);
+    let tokens = lexer.parse_latin1(&latin1).collect::<Result<Vec<_>, _>>().unwrap();
+
+    assert_eq!(tokens[0], UnitToken { lexeme: &latin1[0..4], class: 1 });
+    assert_eq!(tokens[1], UnitToken { lexeme: &latin1[5..7], class: 1 });
+    assert_eq!(tokens[2], UnitToken { lexeme: &latin1[7..8], class: 2 });
+
+    let text = "\u{1F600}\u{1F64F}ok \u{1F650}";
+
+    let utf16 = text.encode_utf16().collect::<Vec<_>>();
+    let lexer = lex_def.compile_with(Encoding::Utf16);
+    let tokens = lexer.parse_utf16(&utf16).collect::<Vec<_>>();
+
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(*tokens[0].as_ref().unwrap(), UnitToken { lexeme: &utf16[0..6], class: 1 });
+
+    // errors locate and skip whole surrogate pairs, as with chars of UTF-8
+    let error = tokens[1].as_ref().unwrap_err();
+    assert_eq!((error.found, error.found_at.column, error.unlexable), (Some('\u{1F650}'), 6, Span { start: 7, end: 9 }));
+}
+
+#[test]
+fn token_positions() {
+    let lexer = lex_def! {
+        [skip] _ws: re::literal(" ").or(&re::literal("\n")).or(&re::literal("\r")).or(&re::literal("\u{2028}")).plus(),
+        word:       re::range('a', 'z').or(&re::literal("\u{E9}")).plus(),
+    }.1.compile();
+
+    let text = "caf\u{E9} au\r\nlait\r\r ok\u{2028}fin\n";
+    let tokens = lexer.parse(text).collect::<Result<Vec<_>, _>>().unwrap();
+
+    let expected = [
+        ("caf\u{E9}", 0, 5, 1, 1),
+        ("au",       6, 8, 1, 6),
+        ("lait",    10, 14, 2, 1),
+        ("ok",      17, 19, 4, 2),
+        ("fin",     22, 25, 5, 1),
+    ];
+
+    for (token, &(lexeme, start, end, line, column)) in tokens.iter().zip(&expected) {
+        assert_eq!(*token, Token { lexeme, class: 1, span: Span { start, end }, start: Position { line, column } });
+    }
+
+    // a `\n` token continues the line break of a `\r` token before it
+    let lexer = lex_def! {
+        cr:   re::literal("\r"),
+        lf:   re::literal("\n"),
+        word: re::range('a', 'z').plus(),
+    }.1.compile();
+
+    let text = "a\r\nb";
+    let tokens = lexer.parse(text).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(tokens[2].end(text), tokens[3].start);
+    assert_eq!(tokens[3].start, Position { line: 2, column: 1 });
+}
+
+#[test]
+fn errors() {
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws: re::literal(" ").or(&re::literal("\n")).plus(),
+        string:     re::literal("\"").then(&re::range('a', 'z').star()).then(&re::literal("\"")),
+        keyword:    re::literal("let"),
+        ident:      re::range('a', 'z').plus().then(&re::literal("!")),
+    };
+    let lexer = lex_def.compile();
+
+    let error = lexer.parse("\"ok\"\n  \"abc").last().unwrap().unwrap_err();
+    assert_eq!(error.span, Span { start: 7, end: 11 });
+    assert_eq!(error.start, Position { line: 2, column: 3 });
+    assert_eq!(error.found, None);
+    assert_eq!(error.expected, &[1]);
+    assert_eq!(error.message(&labels), "unterminated string starting at 2:3");
+
+    let error = lexer.parse("\"ab\u{E9}\"").last().unwrap().unwrap_err();
+    assert_eq!(error.span, Span { start: 0, end: 3 });
+    assert_eq!(error.found, Some('\u{E9}'));
+    assert_eq!(error.found_at, Position { line: 1, column: 4 });
+    assert_eq!(error.message(&labels), "unexpected '\u{E9}' at 1:4 in string starting at 1:1");
+
+    let error = lexer.parse("le?").last().unwrap().unwrap_err();
+    assert_eq!(error.expected, &[2, 3]);
+    assert_eq!(error.message(&labels), "unexpected '?' at 1:3 in one of keyword, ident starting at 1:1");
+    assert_eq!(error.to_string(), "unexpected '?' at 1:3 in one of class 2, class 3 starting at 1:1");
+
+    let error = lexer.parse(" ?").last().unwrap().unwrap_err();
+    assert_eq!(error.message(&labels), "unexpected '?' at 1:2");
+}
+
+#[test]
+fn error_recovery() {
+    let (_, lex_def) = lex_def! {
+        [skip] _ws: re::literal(" ").or(&re::literal("\n")).plus(),
+        keyword:    re::literal("let"),
+        ident:      re::range('a', 'z').plus(),
+        equals:     re::literal("="),
+    };
+    let lexer = lex_def.compile();
+
+    let text = "let x ?= \u{E9}y\n#z";
+
+    // stops at the first error by default
+    assert_eq!(lexer.parse(text).filter(Result::is_err).count(), 1);
+
+    let items: Vec<_> = lexer.parse(text).recover().collect();
+    let tokens: Vec<_> = items.iter().filter_map(|item| item.as_ref().ok()).map(|token| (token.lexeme, token.class)).collect();
+    let errors: Vec<_> = items.iter().filter_map(|item| item.as_ref().err()).collect();
+
+    assert_eq!(tokens, &[("let", 1), ("x", 2), ("=", 3), ("y", 2), ("z", 2)]);
+    assert_eq!(errors.len(), 3);
+    assert_eq!(errors[0].unlexable, Span { start: 6, end: 7 });
+    assert_eq!(errors[1].unlexable, Span { start: 9, end: 11 });
+    assert_eq!(errors[2].unlexable, Span { start: 13, end: 14 });
+    assert_eq!(errors[2].start, Position { line: 2, column: 1 });
+
+    // positions after a skipped char stay correct
+    let z = items.last().unwrap().as_ref().unwrap();
+    assert_eq!(z.start, Position { line: 2, column: 2 });
+}
+
+#[test]
+fn modes() {
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws:                     re::literal(" ").plus(),
+        ident:                          re::range('a', 'z').plus(),
+        [emit, push(string)] open:      re::literal("\""),
+        [emit, pop] interp_close:       re::literal("}"),
+        <string> text:                  re::literal("\"").or(&re::literal("{")).not().plus(),
+        <string> [emit, push(INITIAL)] interp_open: re::literal("{"),
+        <string> [emit, pop] close:     re::literal("\""),
+        [skip, switch(comment)] _begin: re::literal("#"),
+        <comment> [skip] _comment:      re::literal("\n").not().plus(),
+        <comment, INITIAL> [skip, switch(INITIAL)] _newline: re::literal("\n"),
+    };
+    let lexer = lex_def.compile();
+
+    assert_eq!(lex_def.modes, &[vec![0, 1, 2, 3, 7, 9], vec![4, 5, 6], vec![8, 9]]);
+
+    let tokens = |text| lexer.parse(text)
+        .map(|token| { let token = token.unwrap(); (token.lexeme, labels[token.class].as_str()) })
+        .collect::<Vec<_>>();
+
+    assert_eq!(tokens("x \"a {y \"b\"} c\" z"), &[
+        ("x", "ident"), ("\"", "open"), ("a ", "text"), ("{", "interp_open"),
+        ("y", "ident"), ("\"", "open"), ("b", "text"), ("\"", "close"), ("}", "interp_close"),
+        (" c", "text"), ("\"", "close"), ("z", "ident"),
+    ]);
+
+    // rules only match in their own modes
+    assert_eq!(tokens("a # \"b\" }\nc}"), &[("a", "ident"), ("c", "ident"), ("}", "interp_close")]);
+
+    let mut parse = lexer.parse("\"x");
+    assert_eq!(parse.mode(), 0);
+    parse.next();
+    assert_eq!(parse.mode(), 1);
+    assert_eq!(parse.next().unwrap().unwrap().class, 4);
+    assert!(parse.next().is_none());
+
+    // modes are also tracked over encoded input
+    let lexer = lex_def.compile_with(Encoding::Utf16);
+    let text: Vec<u16> = "\"a{b}\"".encode_utf16().collect();
+    let classes: Vec<_> = lexer.parse_utf16(&text).map(|token| token.unwrap().class).collect();
+    assert_eq!(classes, &[2, 4, 5, 1, 3, 6]);
+}
+
+#[test]
+fn commands() {
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws:                       re::literal(" ").plus(),
+        [error("reserved keyword")] goto: re::literal("goto"),
+        [callback(|lexeme| if lexeme.len() > 8 {
+            Command::Error("identifier too long".to_string())
+        } else {
+            Command::Emit
+        })] ident:                        re::range('a', 'z').plus(),
+        [type(ident)] raw:                re::literal("r#").then(&re::range('a', 'z').plus()),
+        [more, push(string)] quote:       re::literal("\""),
+        <string> [more] _chars:           re::literal("\"").or(&re::literal("\\")).not().plus(),
+        <string> [more] _escape:          re::literal("\\").then(&re::literal("\n").or(&re::literal("\n").not())),
+        <string> [emit, pop] string:      re::literal("\""),
+    };
+    let lexer = lex_def.compile();
+
+    let items: Vec<_> = lexer.parse("goto x r#goto \"a\\\"b\" abcdefghi y").recover().collect();
+    let tokens: Vec<_> = items.iter().filter_map(|item| item.as_ref().ok()).map(|token| (token.lexeme, labels[token.class].as_str())).collect();
+    let errors: Vec<_> = items.iter().filter_map(|item| item.as_ref().err()).map(|error| error.message(&labels)).collect();
+
+    assert_eq!(tokens, &[("x", "ident"), ("r#goto", "ident"), ("\"a\\\"b\"", "string"), ("y", "ident")]);
+    assert_eq!(errors, &["reserved keyword at 1:1", "identifier too long at 1:22"]);
+
+    // tokens continued by more are unterminated at the end of input
+    let error = lexer.parse("x \"ab").last().unwrap().unwrap_err();
+    assert_eq!(error.span, Span { start: 2, end: 5 });
+    assert_eq!(error.message(&labels), "unterminated quote starting at 1:3");
+
+    // errors stop lexing outside of recovery mode
+    assert_eq!(lexer.parse("goto x").count(), 1);
+
+    let lexer = lex_def.compile_with(Encoding::Utf16);
+    let text: Vec<u16> = "r#ab \"c\" goto".encode_utf16().collect();
+    let items: Vec<_> = lexer.parse_utf16(&text).map(|item| item.map(|token| token.class).map_err(|error| error.message(&labels))).collect();
+    assert_eq!(items, &[Ok(2), Ok(7), Err("reserved keyword at 1:10".to_string())]);
+}
+
+#[test]
+fn analysis() {
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws: re::any(" \n").star(),
+        ident:      re::range('a', 'z').plus(),
+        keyword:    re::literal("let"),
+        octal:      re::literal("0").then(&re::range('0', '7').plus()),
+        number:     re::range('0', '9').plus(),
+        <string> _chars: re::literal("\"").not().plus(),
+    };
+
+    let diagnostics = lex_def.analyze();
+    assert_eq!(diagnostics, &[
+        Diagnostic::Nullable { class: 0 },
+        Diagnostic::Unreachable { class: 2 },
+        Diagnostic::Unreachable { class: 5 },
+        Diagnostic::Overlap { classes: (1, 2), example: "let".to_string() },
+        Diagnostic::Overlap { classes: (3, 4), example: "00".to_string() },
+    ]);
+
+    assert!(diagnostics[0].is_error());
+    assert!(!diagnostics[1].is_error());
+    assert_eq!(diagnostics[1].message(&labels), "keyword is never matched");
+    assert_eq!(diagnostics[3].message(&labels), "ident and keyword both match \"let\", which lexes as ident");
+    assert_eq!(diagnostics[4].to_string(), "class 3 and class 4 both match \"00\", which lexes as class 3");
+
+    let (_, lex_def) = lex_def! {
+        [emit, push(string)] quote: re::literal("\""),
+        <string> [emit, pop] text:  re::literal("\"").not().plus(),
+        <comment> _comment:         re::literal("\n").not().plus(),
+    };
+    assert_eq!(lex_def.analyze(), &[Diagnostic::Unreachable { class: 2 }]);
+}
+
+#[test]
+fn streaming() {
+    /// Reader that returns at most `size` bytes at a time.
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        size:  usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.size == 0 {
+                return Err(io::Error::new(io::ErrorKind::Other, "broken pipe"));
+            }
+            let n = self.size.min(buf.len()).min(self.bytes.len());
+            buf[..n].copy_from_slice(&self.bytes[..n]);
+            self.bytes = &self.bytes[n..];
+            Ok(n)
+        }
+    }
+
+    let lexer = lex_def! {
+        [skip] _ws: re::any(" \r\n").plus(),
+        word:       re::range('a', 'z').or(&re::literal("\u{E9}")).plus(),
+        arrow:      re::literal("-").plus().then(&re::literal(">")),
+        minus:      re::literal("-"),
+        string:     re::literal("\"").then(&re::literal("\"").not().star()).then(&re::literal("\"")),
+    }.1.compile();
+
+    let text = format!("caf\u{E9} --- -->\r\n\"{}\" ok ---", "x".repeat(20_000)).repeat(3);
+    let expected = lexer.parse(&text).collect::<Result<Vec<_>, _>>().unwrap();
+
+    for &size in &[1, 3, 7, 4096, usize::MAX] {
+        let tokens = lexer.parse_reader(Trickle { bytes: text.as_bytes(), size }).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(tokens.len(), expected.len());
+        for (token, expected) in tokens.iter().zip(&expected) {
+            assert_eq!((token.lexeme.as_str(), token.class, token.span, token.start), (expected.lexeme, expected.class, expected.span, expected.start));
+        }
+    }
+
+    let text = "ok\n caf\u{E9}? \"abc";
+    let expected: Vec<_> = lexer.parse(text).recover().map(|item| item.map(|token| token.lexeme.to_string())).collect();
+    let items: Vec<_> = lexer.parse_reader(Trickle { bytes: text.as_bytes(), size: 2 }).recover()
+        .map(|item| item.map(|token| token.lexeme).map_err(|error| match error {
+            StreamError::Lex(error) => error,
+            StreamError::Io(error) => panic!("{}", error),
+        }))
+        .collect();
+    assert_eq!(items, expected);
+
+    let mut parse = lexer.parse_reader(Trickle { bytes: b"ok", size: 0 });
+    assert!(matches!(parse.next(), Some(Err(StreamError::Io(_)))));
+    assert!(parse.next().is_none());
+}
+
+#[test]
+fn linear_maximal_munch() {
+    let (_, lex_def) = lex_def! {
+        a:  re::literal("a"),
+        ab: re::literal("a").star().then(&re::literal("b")),
+    };
+    let lexer = lex_def.compile();
+
+    // without memoizing failed scans, each token rescans the rest of the input
+    let n = 100_000;
+    let text = "a".repeat(n);
+
+    let tokens = lexer.parse(&text).collect::<Result<Vec<_>, _>>().unwrap();
+    let scanned: usize = lexer.parse_traced(&text).map(|scan| scan.states.len() - 1).sum();
+
+    assert_eq!(tokens.len(), n);
+    assert!(scanned <= 3 * n, "scanned {} bytes to lex {} tokens", scanned, n);
+    assert!(tokens.iter().all(|token| token.class == 0));
+    assert_eq!(lexer.parse("aaab").map(|token| token.unwrap().class).collect::<Vec<_>>(), &[1]);
+
+    let units: Vec<u16> = text.encode_utf16().collect();
+    assert_eq!(lex_def.compile_with(Encoding::Utf16).parse_utf16(&units).count(), n);
+    assert_eq!(lexer.parse_reader(text.as_bytes()).count(), n);
+
+    // failures found by earlier scans still report how far the token got
+    let lexer = lex_def! {
+        b:   re::literal("b"),
+        abc: re::literal("a").or(&re::literal("b")).star().then(&re::literal("c")),
+    }.1.compile();
+
+    let spans: Vec<_> = lexer.parse("baba").recover().map(|item| item.map_or_else(|error| error.span, |token| token.span)).collect();
+    assert_eq!(spans, &[Span { start: 0, end: 1 }, Span { start: 1, end: 4 }, Span { start: 2, end: 3 }, Span { start: 3, end: 4 }]);
+}
+
+#[test]
+fn incremental() {
+    let lexer = lex_def! {
+        [skip] _ws:                re::any(" \r\n").plus(),
+        ident:                     re::range('a', 'z').plus(),
+        arrow:                     re::literal("-").plus().then(&re::literal(">")),
+        minus:                     re::literal("-"),
+        [emit, push(string)] open: re::literal("\""),
+        <string> text:             re::literal("\"").or(&re::literal("\n")).not().plus(),
+        <string> [emit, pop] close: re::literal("\""),
+    }.1.compile();
+
+    let line = "let x -- -> \"some text\" y\r\n";
+    let mut text = line.repeat(50);
+    let mut buffer = lexer.parse_incremental(&text);
+
+    let mut edit = |text: &mut String, start: usize, end: usize, replacement: &str| {
+        text.replace_range(start..end, replacement);
+        let range = buffer.edit(Edit { span: Span { start, end }, len: replacement.len() }, text);
+
+        let expected = lexer.parse(text).recover().collect::<Vec<_>>();
+        let items = buffer.items(text).map(|item| item.map_err(Clone::clone)).collect::<Vec<_>>();
+        assert_eq!(items, expected);
+        range.len()
+    };
+
+    let at = |n: usize| n * line.len();
+
+    // local edits only lex a few tokens, made from the end so that earlier lines stay put
+    assert!(edit(&mut text, at(40) + 4, at(40) + 5, "xyz") <= 2);
+    assert!(edit(&mut text, at(30) + 7, at(30) + 7, ">") <= 3);
+    assert!(edit(&mut text, at(20), at(20), "\n\n") <= 2);
+    assert!(edit(&mut text, at(10) + 13, at(10) + 17, "other") <= 3);
+
+    // unbalanced quotes change the mode of everything after them
+    assert!(edit(&mut text, at(5) + 2, at(5) + 2, "\"") > 100);
+    assert!(edit(&mut text, at(5) + 2, at(5) + 3, "") > 100);
+
+    // errors, and edits at either end
+    edit(&mut text, at(5) + 3, at(5) + 3, "?");
+    edit(&mut text, 0, 3, "");
+    let len = text.len();
+    edit(&mut text, len, len, "z \"");
+    edit(&mut text, 0, len / 2, "a");
+    let len = text.len();
+    edit(&mut text, 0, len, "");
+}
+
+#[test]
+fn values() {
+    let digits = re::range('0', '9').plus();
+    let escape = re::literal("\\").then(&re::range(' ', '~'));
+    let string_char = re::range(' ', '!').or(&re::range('#', '[')).or(&re::range(']', '~')).or(&escape);
+    let char_char = re::range(' ', '&').or(&re::range('(', '[')).or(&re::range(']', '~')).or(&escape);
+
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws: re::literal(" ").or(&re::literal("\n")).plus(),
+        hex:        re::literal("0x").then(&re::range('0', '9').or(&re::range('a', 'f')).plus()) => hex,
+        octal:      re::literal("0o").then(&re::range('0', '7').plus()) => octal,
+        float:      digits.then(&re::literal(".")).then(&digits) => float,
+        int:        digits.then(&re::literal("_").then(&digits).star()) => decimal,
+        string:     re::literal("\"").then(&string_char.star()).then(&re::literal("\"")) => string(Escapes::rust()),
+        char:       re::literal("'").then(&char_char.plus()).then(&re::literal("'")) => char(Escapes::quotes()),
+        word:       re::range('a', 'z').plus() => custom(|lexeme| if lexeme.len() <= 3 {
+            Ok(Value::Str(lexeme.to_uppercase()))
+        } else {
+            Err("word too long".to_string())
+        }),
+        semi:       re::literal(";"),
+    };
+    let lexer = lex_def.compile();
+
+    let text = "0xff 0o17 1.5 1_000 \"a\\\"b\\u{e9}\\n\" '\\'' abc ;";
+    let values: Vec<_> = lexer.parse(text).values().map(|item| item.unwrap().value).collect();
+    assert_eq!(values, &[
+        Some(Value::Int(255)),
+        Some(Value::Int(15)),
+        Some(Value::Float(1.5)),
+        Some(Value::Int(1000)),
+        Some(Value::Str("a\"b\u{e9}\n".to_string())),
+        Some(Value::Char('\'')),
+        Some(Value::Str("ABC".to_string())),
+        None,
+    ]);
+
+    // conversion errors are lexical errors at the offending part of the token
+    let text = "99999999999999999999 \"ab\\qc\"\n'ab' 'c' abcd ;";
+    let items: Vec<_> = lexer.parse(text).recover().values().collect();
+    let errors: Vec<_> = items.iter().filter_map(|item| item.as_ref().err()).map(|error| (error.message(&labels), error.found, error.found_at)).collect();
+    assert_eq!(errors, &[
+        ("integer literal out of range at 1:1".to_string(), Some('9'), Position { line: 1, column: 1 }),
+        ("unknown escape \\q at 1:22".to_string(), Some('\\'), Position { line: 1, column: 25 }),
+        ("char literal must contain exactly one char at 2:1".to_string(), Some('\''), Position { line: 2, column: 1 }),
+        ("word too long at 2:10".to_string(), Some('a'), Position { line: 2, column: 10 }),
+    ]);
+    assert_eq!(items.len(), 6);
+
+    // errors stop lexing outside of recovery mode
+    assert_eq!(lexer.parse("0o7 99999999999999999999 1").values().count(), 2);
+}
+
+#[test]
+fn layout() {
+    let (labels, mut lex_def) = lex_def! {
+        [skip] _ws: re::any(" \t\n\u{2028}").plus(),
+        ident:      re::range('a', 'z').plus(),
+        number:     re::range('0', '9').plus(),
+        equals:     re::literal("="),
+        colon:      re::literal(":"),
+        comma:      re::literal(","),
+        lparen:     re::literal("("),
+        rparen:     re::literal(")"),
+        INDENT:     RegEx::none(),
+        DEDENT:     RegEx::none(),
+        NEWLINE:    RegEx::none(),
+    };
+    lex_def.layout = Some(Layout { indent: 8, dedent: 9, newline: 10, tab_width: 8, brackets: vec![(6, 7)] });
+    assert!(lex_def.analyze().iter().all(|diagnostic| !matches!(diagnostic, Diagnostic::Unreachable { .. })));
+    let lexer = lex_def.compile();
+
+    let classes = |text| lexer.parse(text).layout().map(|item| labels[item.unwrap().class].as_str()).collect::<Vec<_>>().join(" ");
+
+    // brackets join lines, and tabs indent past multiples of the tab width
+    let text = "if x:\n    a = (1,\n  2)\n    if y:\n  \tb\nc\n";
+    assert_eq!(classes(text), "ident ident colon NEWLINE INDENT ident equals lparen number comma number rparen NEWLINE \
+                               ident ident colon NEWLINE INDENT ident NEWLINE DEDENT DEDENT ident NEWLINE");
+
+    let tokens: Vec<_> = lexer.parse("a\n  b").layout().map(Result::unwrap).collect();
+    assert_eq!(tokens[1], Token { lexeme: "", class: 10, span: Span { start: 1, end: 1 }, start: Position { line: 1, column: 2 } });
+    assert_eq!(tokens[2], Token { lexeme: "", class: 8, span: Span { start: 4, end: 4 }, start: Position { line: 2, column: 3 } });
+    assert_eq!(classes(""), "");
+
+    // lines break wherever positions do
+    assert_eq!(classes("a\n  b\u{2028}c"), "ident NEWLINE INDENT ident NEWLINE DEDENT ident NEWLINE");
+
+    // inconsistent dedents are errors
+    let text = "a\n    b\n  c\n d";
+    let items: Vec<_> = lexer.parse(text).recover().layout().collect();
+    let errors: Vec<_> = items.iter().filter_map(|item| item.as_ref().err()).map(|error| error.message(&labels)).collect();
+    assert_eq!(errors, &[
+        "unindent does not match any outer indentation level at 3:3",
+        "unindent does not match any outer indentation level at 4:2",
+    ]);
+    assert_eq!(items.len(), 14);
+    assert_eq!(lexer.parse(text).layout().count(), 7);
+}
+
+#[test]
+fn trace() {
+    let digits = re::range('0', '9').plus();
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws: re::literal(" ").plus(),
+        float:      digits.then(&re::literal(".")).then(&digits),
+        int:        re::range('0', '9').plus(),
+        dot:        re::literal("."),
+        ident:      re::range('a', 'z').plus(),
+    };
+    let lexer = lex_def.compile();
+
+    let text = "12.x ? ab";
+    let scans: Vec<_> = lexer.parse_traced(text).collect();
+    let summary: Vec<_> = scans.iter().map(|scan| (scan.start, scan.class(), scan.end(), scan.backtracked())).collect();
+    assert_eq!(summary, &[
+        (0, Some(2), Some(2), 2),
+        (2, Some(3), Some(3), 1),
+        (3, Some(4), Some(4), 1),
+        (4, Some(0), Some(5), 1),
+        (5, None, None, 1),
+        (6, Some(0), Some(7), 1),
+        (7, Some(4), Some(9), 0),
+    ]);
+    assert!(scans[0].sink);
+    assert!(!scans[6].sink);
+
+    // state ids depend on how the dfas were built
+    let state = |scan: &Scan, len: usize| format!("s{}", scan.states[len].0);
+    assert_eq!(scans[0].pretty(text, &labels), format!("\
+scan at 0 in mode 0
+    start  -> {}
+    '1'    -> {:<6} accepts int
+    '2'    -> {:<6} accepts int
+    '.'    -> {}
+    'x'    -> sink
+    matched \"12\" as int, backtracking 2 bytes
+", state(&scans[0], 0), state(&scans[0], 1), state(&scans[0], 2), state(&scans[0], 3)));
+    assert_eq!(scans[4].pretty(text, &labels), format!("\
+scan at 5 in mode 0
+    start  -> {}
+    '?'    -> sink
+    matched nothing, backtracking 1 byte
+", state(&scans[4], 0)));
+
+    // traces follow the lexer through memoized failures, nested rules and accelerated states
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws:     re::literal(" ").plus(),
+        [skip] comment: nested("/*", "*/"),
+        a:              re::literal("a"),
+        ab:             re::literal("a").star().then(&re::literal("b")),
+        string:         re::literal("\"").then(&re::literal("\"").not().star()).then(&re::literal("\"")),
+    };
+    let lexer = lex_def.compile();
+
+    let text = "aaa /* x /* y */ */ \"a long string\"";
+    let scans: Vec<_> = lexer.parse_traced(text).collect();
+    assert_eq!(scans.iter().map(|scan| (scan.start, scan.end(), scan.memoized)).collect::<Vec<_>>(), &[
+        (0, Some(1), false),
+        (1, Some(2), true),
+        (2, Some(3), false),
+        (3, Some(4), false),
+        (4, Some(19), false),
+        (19, Some(20), false),
+        (20, Some(text.len()), false),
+    ]);
+    assert!(scans[1].pretty(text, &labels).contains("known to fail from here"));
+    assert!(scans[4].pretty(text, &labels).contains("matched \"/* x /* y */ */\" as comment"));
+
+    let string = &scans[6];
+    assert!(string.states.iter().any(|&(state, _)| lexer.accel[state].is_some()));
+    assert_eq!(string.states.len(), text.len() - string.start + 1);
+}
+
+#[test]
+fn nested() {
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws:      re::any(" \n").plus(),
+        [skip] comment:  nested("/*", "*/"),
+        <INITIAL, doc> [emit, push(doc)] open: nested("{", "}"),
+        slash:           re::literal("/"),
+        star:            re::literal("*"),
+        ident:           re::range('a', 'z').plus(),
+    };
+    let lexer = lex_def.compile();
+
+    let lexemes = |text| lexer.parse(text).map(|item| item.map(|token| (token.lexeme, labels[token.class].as_str())).map_err(|error| error.message(&labels))).collect::<Vec<_>>();
+
+    // comments nest, and only their open delimiter competes with other rules
+    assert_eq!(lexemes("a /* b /* c */ d */ / * e {x {y} z}"), &[
+        Ok(("a", "ident")),
+        Ok(("/", "slash")),
+        Ok(("*", "star")),
+        Ok(("e", "ident")),
+        Ok(("{x {y} z}", "open")),
+    ]);
+    assert_eq!(lexemes("a /**/ /*/ b */ c"), &[Ok(("a", "ident")), Ok(("c", "ident"))]);
+
+    // comments must be closed before the end of input
+    assert_eq!(lexemes("a /* b /* c */\nd"), &[Ok(("a", "ident")), Err("unterminated comment starting at 1:3".to_string())]);
+    let items: Vec<_> = lexer.parse("a /* b /* c */\nd e").recover().collect();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[1].as_ref().unwrap_err().span, Span { start: 2, end: 18 });
+
+    let lexer = lex_def.compile_with(Encoding::Utf16);
+    let text: Vec<u16> = "/* \u{E9} /* */ */ x {".encode_utf16().collect();
+    let items: Vec<_> = lexer.parse_utf16(&text).map(|item| item.map(|token| token.class).map_err(|error| error.message(&labels))).collect();
+    assert_eq!(items, &[Ok(5), Err("unterminated open starting at 1:17".to_string())]);
+
+    // comments longer than the stream's buffer
+    let lexer = lex_def.compile();
+    let text = format!("a /* {} */ b", "/* */ ".repeat(5_000));
+    let tokens: Vec<_> = lexer.parse_reader(text.as_bytes()).map(|item| item.unwrap().lexeme).collect();
+    assert_eq!(tokens, &["a", "b"]);
+}
+
+#[test]
+fn external_scanners() {
+    /// Ends raw strings at a quote followed by as many hashes as the prefix.
+    fn raw_string(text: &str, prefix: usize, complete: bool) -> Scanned {
+        let close = format!("\"{}", "#".repeat(prefix - 2));
+        match text[prefix..].find(&close) {
+            Some(i) => Scanned::Token { len: prefix + i + close.len(), read: prefix + i + close.len() },
+            None if complete => Scanned::Error { reason: "unterminated raw string".to_string(), read: text.len() },
+            None => Scanned::More,
+        }
+    }
+
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws: re::any(" \n").plus(),
+        raw:        scan(re::literal("r").then(&re::literal("#").star()).then(&re::literal("\"")), raw_string),
+        heredoc:    scan(re::literal("<<").then(&re::range('A', 'Z').plus()).then(&re::literal("\n")), |text, prefix, complete| {
+            let tag = &text[2..prefix - 1];
+            let mut start = prefix;
+            for line in text[prefix..].split_inclusive('\n') {
+                // the last line may continue past the text
+                if line.trim_end_matches('\n') == tag && (line.ends_with('\n') || complete) {
+                    return Scanned::Token { len: start + tag.len(), read: start + line.len() };
+                }
+                start += line.len();
+            }
+
+            if complete { Scanned::Error { reason: format!("missing {tag}"), read: text.len() } } else { Scanned::More }
+        }),
+        ident:      re::range('a', 'z').plus(),
+    };
+    let lexer = lex_def.compile();
+
+    let text = "r#\"a \"b\" c\"# rx r\"\u{E9}\" <<END\nx\nEND\n y";
+    let tokens: Vec<_> = lexer.parse(text).map(|item| item.map(|token| token.lexeme).unwrap()).collect();
+    assert_eq!(tokens, &["r#\"a \"b\" c\"#", "rx", "r\"\u{E9}\"", "<<END\nx\nEND", "y"]);
+
+    // scanners reject tokens, which end after the prefix
+    let items: Vec<_> = lexer.parse("a r##\"b\"# c").recover().map(|item| item.map(|token| token.lexeme).map_err(|error| (error.message(&labels), error.span))).collect();
+    assert_eq!(items, &[
+        Ok("a"),
+        Err(("unterminated raw string at 1:3".to_string(), Span { start: 2, end: 6 })),
+        Ok("b"),
+        Err(("unexpected '\"' at 1:8".to_string(), Span { start: 7, end: 7 })),
+        Err(("unexpected '#' at 1:9".to_string(), Span { start: 8, end: 8 })),
+        Ok("c"),
+    ]);
+    assert_eq!(lexer.parse("<<EOF\nx").map(|item| item.map_err(|error| error.message(&labels))).collect::<Vec<_>>(), &[Err("missing EOF at 1:1".to_string())]);
+
+    let lexer = lex_def.compile_with(Encoding::Utf16);
+    let text: Vec<u16> = "r#\"\u{E9}\"# x".encode_utf16().collect();
+    let tokens: Vec<_> = lexer.parse_utf16(&text).map(|item| String::from_utf16(item.unwrap().lexeme).unwrap()).collect();
+    assert_eq!(tokens, &["r#\"\u{E9}\"#", "x"]);
+
+    // tokens longer than the stream's buffer
+    let lexer = lex_def.compile();
+    let text = format!("a r#\"{}\"# b r\"c", "\"".repeat(20_000));
+    let items: Vec<_> = lexer.parse_reader(text.as_bytes()).recover().map(|item| item.map(|token| token.lexeme.len()).map_err(|error| error.to_string())).collect();
+    assert_eq!(items, &[Ok(1), Ok(20_005), Ok(1), Err("unterminated raw string at 1:20011".to_string()), Ok(1)]);
+
+    // scanners that end tokens inside chars are errors rather than panics
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws: re::any(" ").plus(),
+        bad:        scan(re::literal("!"), |_, _, _| Scanned::Token { len: 2, read: 2 }),
+        ident:      re::range('a', 'z').plus(),
+    };
+    let bad = lex_def.compile();
+    let items: Vec<_> = bad.parse("!\u{E9} a").recover().map(|item| item.map(|token| token.lexeme).map_err(|error| error.message(&labels))).collect();
+    assert_eq!(items, &[
+        Err("scanner ended the token at 2, which is not a char boundary of the scanned text at 1:1".to_string()),
+        Err("unexpected '\u{E9}' at 1:2".to_string()),
+        Ok("a"),
+    ]);
+
+    // edits past the text a scanner read only relex nearby tokens
+    let text = format!("r\"a\" {}", "b ".repeat(100));
+    let mut buffer = lexer.parse_incremental(&text);
+    let mut edited = text.clone();
+    edited.replace_range(200..201, "c");
+    assert!(buffer.edit(Edit { span: Span { start: 200, end: 201 }, len: 1 }, &edited).len() <= 2);
+
+    // scanners are given bounded windows, so many scanned tokens take linear time
+    static SCANNED: AtomicUsize = AtomicUsize::new(0);
+    fn counted(text: &str, prefix: usize, complete: bool) -> Scanned {
+        SCANNED.fetch_add(text.len(), Ordering::Relaxed);
+        raw_string(text, prefix, complete)
+    }
+
+    let (_, lex_def) = lex_def! {
+        [skip] _ws: re::any(" \n").plus(),
+        raw:        scan(re::literal("r").then(&re::literal("#").star()).then(&re::literal("\"")), counted),
+    };
+    let lexer = lex_def.compile();
+    assert_eq!(lexer.parse(&"r\"a\" ".repeat(4_000)).count(), 4_000);
+    assert!(SCANNED.load(Ordering::Relaxed) < 4_000 * 300);
+}
+
+#[test]
+fn incomplete_input() {
+    let (labels, lex_def) = lex_def! {
+        [skip] _ws:     re::any(" \n").plus(),
+        [skip] comment: nested("/*", "*/"),
+        [more, push(string)] quote: re::literal("\""),
+        <string> [more] _chars:     re::literal("\"").not().plus(),
+        <string> [emit, pop] string: re::literal("\""),
+        char:           re::literal("'").then(&re::range('a', 'z')).then(&re::literal("'")),
+        ident:          re::range('a', 'z').plus(),
+    };
+    let lexer = lex_def.compile();
+
+    let error = |text| lexer.parse(text).find_map(Result::err);
+
+    // input ends inside a token that more input could complete
+    for text in &["a \"b c", "a /* b /* */", "a 'b", "a '"] {
+        let error = error(text).unwrap();
+        assert!(error.is_incomplete(), "{} is incomplete", error.message(&labels));
+    }
+
+    // other errors are not
+    for text in &["a ? b", "a 'bc'"] {
+        let error = error(text).unwrap();
+        assert!(!error.is_incomplete(), "{} is not incomplete", error.message(&labels));
+    }
+    assert!(error("a \"b\" c").is_none());
+
+    // input ending part way through a longer token than the one lexed before an error
+    let lexer = lex_def! {
+        a:   re::literal("a"),
+        abc: re::literal("abc"),
+    }.1.compile();
+
+    let items: Vec<_> = lexer.parse("ab").collect();
+    assert_eq!(items[0].as_ref().map(|token| token.class), Ok(0));
+    assert!(items[1].as_ref().unwrap_err().is_incomplete());
+    assert!(!lexer.parse("abd").find_map(Result::err).unwrap().is_incomplete());
+}
+
+#[test]
+fn parallel() {
+    let (_, lex_def) = lex_def! {
+        [skip] _ws:      re::any(" \r\n").plus(),
+        [skip] comment:  nested("/*", "*/"),
+        [more, push(string)] quote: re::literal("\""),
+        <string> [more] _chars:     re::range(' ', '!').or(&re::range('#', '[')).or(&re::range(']', '~')).or(&re::any("\r\n\u{E9}")).plus(),
+        <string> [more] _escape:    re::literal("\\").then(&re::range(' ', '~').or(&re::literal("\n"))),
+        <string> [emit, pop] string: re::literal("\""),
+        ident:           re::range('a', 'z').plus(),
+        number:          re::range('0', '9').plus(),
+        punct:           re::any("{}();=+*/"),
+    };
+    let lexer = lex_def.compile();
+
+    // pseudo-random text where chunks often start inside strings and comments
+    let pieces = ["abc ", "12", " = ", "\n", "\r\n", "\"x\ny\" ", "\"\\\"\n\" ", "/* a\n/* \"b */\n */", "{ f(); }\n", "?", "\u{E9}", "\"\n\n\n"];
+    let mut seed = 7_u64;
+    let mut text = String::new();
+    while text.len() < 500_000 {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        text.push_str(pieces[(seed >> 33) as usize % pieces.len()]);
+    }
+
+    let expected: Vec<_> = lexer.parse(&text).recover().collect();
+    for threads in &[1, 2, 5, 16] {
+        assert!(lexer.parse_parallel(&text, *threads) == expected, "lexing on {} threads differs", threads);
+    }
+    assert_eq!(lexer.parse_parallel("a \"b", 4), lexer.parse("a \"b").recover().collect::<Vec<_>>());
 }
\ No newline at end of file
@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+use crate::debug::StringBuilder;
+use super::{LexAnalyzer, Parse, Unit};
+use super::parse::{Match, Failure, Memo, Observer};
+
+/// Run of the lexer dfa from the start of some token, as recorded by
+/// `LexAnalyzer::parse_traced`. Bytes skipped over by accelerated states are
+/// recorded as staying in those states.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Scan {
+    pub mode:        usize,
+    pub start:       usize,                      // index of the first byte scanned
+    pub states:      Vec<(usize, Option<usize>)>, // states visited and the classes they accept, after reading 0, 1, 2, ... bytes
+    pub sink:        bool,                       // whether the last byte read led to the sink state
+    pub memoized:    bool,                       // whether the scan stopped where an earlier scan was known to fail
+    pub last_accept: Option<usize>,              // number of bytes read up to the last accepting state
+    pub extended:    Option<usize>,              // index past the token, if a nested or external rule extended the match
+}
+
+impl Scan {
+    /// Returns the class of the longest match, if any.
+    #[must_use]
+    pub fn class(&self) -> Option<usize> {
+        self.last_accept.and_then(|len| self.states[len].1)
+    }
+
+    /// Returns the index past the longest match (as extended by nested or
+    /// external rules), if any.
+    #[must_use]
+    pub fn end(&self) -> Option<usize> {
+        self.extended.or_else(|| self.last_accept.map(|len| self.start + len))
+    }
+
+    /// Returns the number of bytes read past the longest match (or past the
+    /// start if nothing matched), which are given back to lex again.
+    #[must_use]
+    pub fn backtracked(&self) -> usize {
+        self.states.len() - 1 - self.last_accept.unwrap_or(0)
+    }
+
+    /// Describes each step of the scan over `text`, naming token classes
+    /// with `labels`, such as `ParserDef::lex_labels`.
+    #[must_use]
+    pub fn pretty<T: AsRef<str>>(&self, text: &str, labels: &[T]) -> String {
+        let bytes = text.as_bytes();
+        let mut builder = StringBuilder::new();
+
+        builder.writeln(&format!("scan at {} in mode {}", self.start, self.mode));
+        builder.indent();
+
+        for (len, &(state, class)) in self.states.iter().enumerate() {
+            let symbol = if len == 0 { "start".to_string() } else { escape(bytes[self.start + len - 1]) };
+            let state = if self.sink && len == self.states.len() - 1 { "sink".to_string() } else { format!("s{state}") };
+
+            match class {
+                Some(class) => builder.writeln(&format!("{:<6} -> {:<6} accepts {}", symbol, state, labels[class].as_ref())),
+                None        => builder.writeln(&format!("{symbol:<6} -> {state}")),
+            }
+        }
+        if self.memoized {
+            builder.writeln("known to fail from here");
+        }
+        if let (Some(end), Some(len)) = (self.extended, self.last_accept) {
+            builder.writeln(&format!("extended by {} bytes", end - self.start - len));
+        }
+
+        match (self.class(), self.end()) {
+            (Some(class), Some(end)) => {
+                builder.write(&format!("matched {:?} as {}", String::from_utf8_lossy(&bytes[self.start..end]), labels[class].as_ref()));
+            },
+            _ => builder.write("matched nothing"),
+        }
+        match self.backtracked() {
+            0 => builder.newline(),
+            1 => builder.writeln(", backtracking 1 byte"),
+            n => builder.writeln(&format!(", backtracking {n} bytes")),
+        }
+
+        builder.unindent();
+        builder.build()
+    }
+}
+
+/// Runs of the lexer dfa made while lexing some text in recovery mode.
+pub struct TracedParse<'a> {
+    parse: Parse<'a>,
+    scans: VecDeque<Scan>,
+    done:  bool,
+}
+
+impl<'a> TracedParse<'a> {
+    pub(super) fn new(parse: Parse<'a>) -> Self {
+        Self { parse, scans: VecDeque::new(), done: false }
+    }
+}
+
+impl Iterator for TracedParse<'_> {
+    type Item = Scan;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(scan) = self.scans.pop_front() {
+                return Some(scan);
+            } else if self.done {
+                return None;
+            }
+
+            self.done = self.parse.next().is_none();
+            self.scans.extend(self.parse.take_scans());
+        }
+    }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+impl LexAnalyzer {
+    /// Finds the longest match from `start` as in `longest_match`, recording
+    /// the run of the dfa of `mode`.
+    pub(super) fn traced_match<U: Unit>(&self, mode: usize, units: &[U], start: usize, at_end: bool, memo: &mut Memo) -> (Result<Match, Failure>, Scan) {
+        let mut scan = Scan { mode, start, states: Vec::new(), sink: false, memoized: false, last_accept: None, extended: None };
+        let found = self.longest_match(mode, units, start, at_end, memo, &mut scan);
+
+        scan.sink = scan.states.last().map_or(false, |&(state, _)| state == self.sink());
+        scan.last_accept = scan.states.iter().rposition(|(_, class)| class.is_some());
+        (found, scan)
+    }
+}
+
+impl Observer for Scan {
+    fn state(&mut self, state: usize, class: Option<usize>) {
+        self.states.push((state, class));
+    }
+
+    fn memoized(&mut self) {
+        self.memoized = true;
+    }
+
+    fn extended(&mut self, end: usize) {
+        self.extended = Some(end);
+    }
+}
+
+fn escape(byte: u8) -> String {
+    format!("'{}'", std::ascii::escape_default(byte))
+}
\ No newline at end of file
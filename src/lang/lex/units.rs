@@ -0,0 +1,152 @@
+use super::{LexAnalyzer, ParseError};
+use crate::lang::re::Encoding;
+use std::borrow::Cow;
+use super::parse::{Lexing, Lexed};
+
+/// Code unit of some encoded input to a `LexAnalyzer`.
+pub trait Unit: Copy {
+    /// Feeds the bytes of the unit to the lexer dfa, starting from `state`.
+    fn step(self, lex: &LexAnalyzer, state: usize) -> usize;
+
+    /// Checks if any byte of the unit is one of `exits`.
+    fn exits(self, exits: &[u8]) -> bool;
+
+    /// Decodes units into text in `encoding`, replacing invalid sequences.
+    fn decode(units: &[Self], encoding: Encoding) -> Cow<'_, str>;
+
+    /// Returns the number of units of the char at the start of `units` in
+    /// `encoding`, taking invalid units as chars of their own.
+    fn char_len(units: &[Self], encoding: Encoding) -> usize;
+
+    /// Checks if `units` start with the units whose bytes are fed to the
+    /// lexer dfa as `bytes`.
+    fn starts_with(units: &[Self], bytes: &[u8]) -> bool;
+
+    /// Decodes the longest prefix of `units` that is valid in `encoding`.
+    fn decode_valid(units: &[Self], encoding: Encoding) -> Cow<'_, str>;
+
+    /// Returns the number of units that encode `text` in `encoding`.
+    fn count(text: &str, encoding: Encoding) -> usize;
+}
+
+impl Unit for u8 {
+    fn step(self, lex: &LexAnalyzer, state: usize) -> usize {
+        lex.step(state, self)
+    }
+
+    fn exits(self, exits: &[u8]) -> bool {
+        exits.contains(&self)
+    }
+
+    /// Bytes are either UTF-8 or Latin-1.
+    fn decode(units: &[Self], encoding: Encoding) -> Cow<'_, str> {
+        match encoding {
+            Encoding::Latin1 => Cow::Owned(units.iter().copied().map(char::from).collect()),
+            _                => String::from_utf8_lossy(units),
+        }
+    }
+
+    fn char_len(units: &[Self], encoding: Encoding) -> usize {
+        let len = match (encoding, units.first()) {
+            (Encoding::Latin1, _)  => 1,
+            (_, Some(0xC0..=0xDF)) => 2,
+            (_, Some(0xE0..=0xEF)) => 3,
+            (_, Some(0xF0..=0xF7)) => 4,
+            _                      => 1,
+        };
+        if units.len() >= len && units[1..len].iter().all(|&byte| byte & 0xC0 == 0x80) { len } else { 1 }
+    }
+
+    fn starts_with(units: &[Self], bytes: &[u8]) -> bool {
+        units.starts_with(bytes)
+    }
+
+    /// Bytes are either UTF-8 or Latin-1.
+    fn decode_valid(units: &[Self], encoding: Encoding) -> Cow<'_, str> {
+        match encoding {
+            Encoding::Latin1 => Self::decode(units, encoding),
+            _ => match std::str::from_utf8(units) {
+                Ok(text) => Cow::Borrowed(text),
+                Err(error) => Cow::Borrowed(std::str::from_utf8(&units[..error.valid_up_to()]).unwrap()),
+            },
+        }
+    }
+
+    fn count(text: &str, encoding: Encoding) -> usize {
+        match encoding {
+            Encoding::Latin1 => text.chars().count(),
+            _                => text.len(),
+        }
+    }
+}
+
+/// UTF-16 code units are fed low byte first.
+impl Unit for u16 {
+    fn step(self, lex: &LexAnalyzer, state: usize) -> usize {
+        let [low, high] = self.to_le_bytes();
+        let state = lex.step(state, low);
+        if state == lex.sink() { state } else { lex.step(state, high) }
+    }
+
+    fn exits(self, exits: &[u8]) -> bool {
+        self.to_le_bytes().iter().any(|byte| exits.contains(byte))
+    }
+
+    fn decode(units: &[Self], _: Encoding) -> Cow<'_, str> {
+        Cow::Owned(String::from_utf16_lossy(units))
+    }
+
+    fn char_len(units: &[Self], _: Encoding) -> usize {
+        match units {
+            [0xD800..=0xDBFF, 0xDC00..=0xDFFF, ..] => 2,
+            _                                       => 1,
+        }
+    }
+
+    fn starts_with(units: &[Self], bytes: &[u8]) -> bool {
+        bytes.len() <= 2 * units.len() && bytes.chunks(2).zip(units).all(|(pair, unit)| pair == unit.to_le_bytes())
+    }
+
+    fn decode_valid(units: &[Self], _: Encoding) -> Cow<'_, str> {
+        Cow::Owned(char::decode_utf16(units.iter().copied()).map_while(Result::ok).collect())
+    }
+
+    fn count(text: &str, _: Encoding) -> usize {
+        text.encode_utf16().count()
+    }
+}
+
+/// Token over input that is not UTF-8, where the lexeme is a slice of code units.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UnitToken<'a, U> {
+    pub lexeme: &'a [U],
+    pub class:  usize,
+}
+
+pub struct UnitParse<'a, U> {
+    lex:    &'a LexAnalyzer,
+    units:  &'a [U],
+    lexing: Lexing,
+}
+
+impl<'a, U: Unit> UnitParse<'a, U> {
+    pub(crate) fn new(lex: &'a LexAnalyzer, units: &'a [U]) -> Self {
+        Self {
+            lex,
+            units,
+            lexing: Lexing::default(),
+        }
+    }
+}
+
+impl<'a, U: Unit> Iterator for UnitParse<'a, U> {
+    type Item = Result<UnitToken<'a, U>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.lexing.next(self.lex, self.units, 0, true)? {
+            Lexed::Token { class, start, end, .. } => Some(Ok(UnitToken { lexeme: &self.units[start..end], class })),
+            Lexed::Error(error) => Some(Err(error)),
+            Lexed::Starved => unreachable!(),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,204 @@
+use std::num::IntErrorKind;
+use super::{LexAnalyzer, Token, Parse, ParseError};
+use super::position::Cursor;
+
+/// Typed value of a token, produced by the converter of its class.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Int(u64),
+    Float(f64),
+    Str(String),
+    Char(char),
+}
+
+/// Escape sequences allowed within string and char literals.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Escapes {
+    /// Char that starts an escape sequence.
+    pub escape: char,
+    /// Chars that may follow `escape`, with the char each sequence stands for.
+    pub chars: Vec<(char, char)>,
+    /// Allows code points written in hex, as in `\u{1F600}`.
+    pub unicode: bool,
+}
+
+impl Escapes {
+    /// Escapes of backslashes and quotes, as in `\\`, `\"` and `\'`.
+    #[must_use]
+    pub fn quotes() -> Self {
+        Self {
+            escape: '\\',
+            chars: vec![('\\', '\\'), ('"', '"'), ('\'', '\'')],
+            unicode: false,
+        }
+    }
+
+    /// Escapes of Rust literals.
+    #[must_use]
+    pub fn rust() -> Self {
+        let mut escapes = Self::quotes();
+        escapes.chars.extend(&[('n', '\n'), ('r', '\r'), ('t', '\t'), ('0', '\0')]);
+        escapes.unicode = true;
+        escapes
+    }
+}
+
+/// Converts the lexemes of a token class to values.
+#[derive(Clone, Debug)]
+pub enum Converter {
+    /// Decimal integer, which may contain `_` separators.
+    Decimal,
+    /// Hex integer, after an optional `0x` prefix.
+    Hex,
+    /// Octal integer, after an optional `0o` prefix.
+    Octal,
+    /// Float, as accepted by `str::parse`, which may contain `_` separators.
+    Float,
+    /// String between quotes (or any other delimiting chars).
+    Str(Escapes),
+    /// Single char between quotes.
+    Char(Escapes),
+    /// User conversion, failing with a message.
+    Custom(fn(&str) -> Result<Value, String>),
+}
+
+impl Converter {
+    /// Converts `lexeme`, or fails with a message and the byte offset of the
+    /// offending part of the lexeme.
+    ///
+    /// # Errors
+    /// Fails if `lexeme` does not denote a value.
+    pub fn convert(&self, lexeme: &str) -> Result<Value, (String, usize)> {
+        match self {
+            Converter::Decimal => int(lexeme, "", 10),
+            Converter::Hex     => int(lexeme, "0x", 16),
+            Converter::Octal   => int(lexeme, "0o", 8),
+            Converter::Float   => {
+                lexeme.replace('_', "").parse().map(Value::Float).map_err(|_| ("invalid float literal".to_string(), 0))
+            },
+            Converter::Str(escapes) => unescape(lexeme, escapes).map(Value::Str),
+            Converter::Char(escapes) => {
+                let text = unescape(lexeme, escapes)?;
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(Value::Char(c)),
+                    _               => Err(("char literal must contain exactly one char".to_string(), 0)),
+                }
+            },
+            Converter::Custom(convert) => convert(lexeme).map_err(|message| (message, 0)),
+        }
+    }
+}
+
+/// Token with the value of its lexeme, if its class has a converter.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ValueToken<'a> {
+    pub token: Token<'a>,
+    pub value: Option<Value>,
+}
+
+/// Tokens of a `Parse` with their values. Failed conversions are errors at
+/// the offending part of the token.
+pub struct Values<'a> {
+    lex:     &'a LexAnalyzer,
+    text:    &'a str,
+    parse:   Parse<'a>,
+    recover: bool,
+    done:    bool,
+}
+
+impl<'a> Values<'a> {
+    pub(super) fn new(lex: &'a LexAnalyzer, text: &'a str, parse: Parse<'a>, recover: bool) -> Self {
+        Self { lex, text, parse, recover, done: false }
+    }
+}
+
+impl<'a> Iterator for Values<'a> {
+    type Item = Result<ValueToken<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let token = match self.parse.next()? {
+            Ok(token) => token,
+            Err(error) => return Some(Err(error)),
+        };
+
+        match self.lex.converters[token.class].as_ref().map(|converter| converter.convert(token.lexeme)) {
+            None => Some(Ok(ValueToken { token, value: None })),
+            Some(Ok(value)) => Some(Ok(ValueToken { token, value: Some(value) })),
+            Some(Err((reason, offset))) => {
+                self.done = !self.recover;
+
+                let mut cursor = Cursor::resume(self.text, token.span.start, token.start);
+                cursor.advance(&token.lexeme[..offset]);
+
+                Some(Err(ParseError {
+                    span:       token.span,
+                    start:      token.start,
+                    found:      token.lexeme[offset..].chars().next(),
+                    found_at:   cursor.position,
+                    expected:   vec![token.class],
+                    unlexable:  token.span,
+                    incomplete: false,
+                    reason:     Some(reason),
+                }))
+            },
+        }
+    }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+fn int(lexeme: &str, prefix: &str, radix: u32) -> Result<Value, (String, usize)> {
+    let digits = lexeme.strip_prefix(prefix)
+        .or_else(|| lexeme.strip_prefix(&prefix.to_uppercase()))
+        .unwrap_or(lexeme)
+        .replace('_', "");
+
+    u64::from_str_radix(&digits, radix).map(Value::Int).map_err(|error| match error.kind() {
+        IntErrorKind::PosOverflow => ("integer literal out of range".to_string(), 0),
+        _                         => ("invalid integer literal".to_string(), 0),
+    })
+}
+
+/// Replaces the escape sequences between the first and last chars of `lexeme`.
+fn unescape(lexeme: &str, escapes: &Escapes) -> Result<String, (String, usize)> {
+    let mut chars = lexeme.char_indices().skip(1);
+    let mut text = String::new();
+    let last = lexeme.char_indices().last().map_or(0, |(i, _)| i);
+
+    while let Some((i, c)) = chars.next() {
+        if i == last {
+            break;
+        } else if c != escapes.escape {
+            text.push(c);
+            continue;
+        }
+
+        let escaped = chars.next().filter(|&(j, _)| j != last).map(|(_, escaped)| escaped);
+        if let Some(&(_, c)) = escapes.chars.iter().find(|&&(escaped_char, _)| Some(escaped_char) == escaped) {
+            text.push(c);
+        } else if escapes.unicode && escaped == Some('u') {
+            let code = lexeme[i..last].strip_prefix(&format!("{}u{{", escapes.escape)).and_then(|rest| rest.split_once('}'));
+            let c = code.and_then(|(digits, _)| u32::from_str_radix(digits, 16).ok()).and_then(char::from_u32);
+            let c = c.ok_or_else(|| ("invalid unicode escape".to_string(), i))?;
+            text.push(c);
+
+            // skip the digits and closing brace
+            let len = code.map_or(0, |(digits, _)| digits.len() + 2);
+            for _ in 0..len {
+                chars.next();
+            }
+        } else {
+            let sequence = escaped.map_or_else(|| escapes.escape.to_string(), |escaped| format!("{}{}", escapes.escape, escaped));
+            return Err((format!("unknown escape {sequence}"), i));
+        }
+    }
+
+    Ok(text)
+}
\ No newline at end of file
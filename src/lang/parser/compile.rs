@@ -1,5 +1,5 @@
 use crate::lang::{lex, syn};
-use super::{Command, Parser};
+use super::{Command, Parser, Rewrite};
 use crate::lang::re::DFA;
 use crate::lang::cfg::lr1::LR1A;
 
@@ -9,6 +9,7 @@ pub struct ParserDef {
     pub lex_def: lex::LexDef,
     pub syn_def: syn::SynDef,
     pub commands: Vec<Command>,
+    pub rewrites: Vec<Rewrite>,
 }
 
 impl ParserDef {
@@ -20,6 +21,7 @@ impl ParserDef {
             lex: self.lex_def.compile(),
             syn: self.syn_def.compile()?,
             commands: self.commands.to_vec(),
+            rewrites: self.rewrites.clone(),
         })
     }
 
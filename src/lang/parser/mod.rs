@@ -3,6 +3,7 @@ use crate::lang::syn;
 use crate::cst::{CST, CSTBuilder};
 
 pub use self::compile::ParserDef;
+pub use self::rewrite::Rewrite;
 
 #[derive(Clone)]
 pub enum Command {
@@ -13,9 +14,10 @@ pub enum Command {
 pub struct Parser {
     pub lex_labels: Vec<String>,
     pub syn_labels: Vec<String>,
-    lex: lex::LexAnalyzer,
+    pub(crate) lex: lex::LexAnalyzer,
     pub syn: syn::SynAnalyzer,
-    commands: Vec<Command>
+    commands: Vec<Command>,
+    rewrites: Vec<Rewrite>,
 }
 
 #[derive(Debug)]
@@ -24,10 +26,30 @@ pub enum ParseError<'a> {
     Syn(Vec<Token<'a>>, syn::ParseError),
 }
 
+impl ParseError<'_> {
+    /// Checks if more input could complete the text, as with an unterminated
+    /// token or unclosed brackets.
+    #[must_use]
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            ParseError::Lex(error) => error.is_incomplete(),
+            ParseError::Syn(_, error) => error.is_incomplete(),
+        }
+    }
+}
+
 impl Parser {
+    /// Lexes `text`, then applies the rewrite rules to the tokens.
+    ///
     /// # Errors
     pub fn tokenize<'a>(&'a self, text: &'a str) -> Result<Vec<Token>, lex::ParseError> {
-        self.lex.parse(text).collect()
+        self.rewrite(text, self.lex.parse(text))
+    }
+
+    /// Describes how the lexer scanned each token of `text`, step by step.
+    #[must_use]
+    pub fn trace(&self, text: &str) -> String {
+        self.lex.parse_traced(text).map(|scan| scan.pretty(text, &self.lex_labels)).collect()
     }
 
     /// # Errors
@@ -35,30 +57,7 @@ impl Parser {
         // let iter = self.lex.parse(text).map(|res| Ok((res?.class, res?.lexeme)));
         // let tokenize = iter.unzip::<Result<(Vec<usize>, Vec<&str>), lex::ParseError>>();
         match self.tokenize(text) {
-            Ok(tokens) => {
-                let mut builder = CSTBuilder::new();
-        
-                for res in self.syn.parse(tokens.iter().map(|token| token.class)) {
-                    match res {
-                        Ok(step) => {
-                            match step {
-                                syn::Node::Word { word, index } => builder.leaf(word, index),
-                                syn::Node::Var { var, child_count } => {
-                                    match self.commands.get(var).unwrap() {
-                                        Command::Emit => builder.branch(var, child_count),
-                                        Command::Skip => builder.list(child_count),
-                                    };
-                                },
-                            }
-                        },
-                        Err(error) => {
-                            return Err(ParseError::Syn(tokens, error));
-                        }
-                    }
-                }
-        
-                Ok(builder.build(tokens))
-            },
+            Ok(tokens) => self.build(tokens),
             Err(error) => {
                 Err(ParseError::Lex(error))
             }
@@ -70,4 +69,47 @@ impl Parser {
 // === INTERNALS ===
 // =================
 
-mod compile;
\ No newline at end of file
+impl Parser {
+    /// Applies the layout of the lexer, if any, and then the rewrite rules
+    /// to lexed `items` of `text`.
+    pub(crate) fn rewrite<'a>(&'a self, text: &'a str, items: impl Iterator<Item = Result<Token<'a>, lex::ParseError>>) -> Result<Vec<Token<'a>>, lex::ParseError> {
+        let tokens = match self.lex.layout() {
+            Some(_) => lex::LayoutParse::new(&self.lex, text, items, false).collect::<Result<_, _>>()?,
+            None    => items.collect::<Result<_, _>>()?,
+        };
+
+        Ok(self.rewrites.iter().fold(tokens, |tokens, rewrite| rewrite.apply(text, tokens)))
+    }
+
+    /// Parses `tokens` into a CST.
+    pub(crate) fn build<'a>(&self, tokens: Vec<Token<'a>>) -> Result<CST<'a>, ParseError<'a>> {
+        let mut builder = CSTBuilder::new();
+
+        for res in self.syn.parse(tokens.iter().map(|token| token.class)) {
+            match res {
+                Ok(step) => {
+                    match step {
+                        syn::Node::Word { word, index } => builder.leaf(word, index),
+                        syn::Node::Var { var, child_count } => {
+                            match self.commands.get(var).unwrap() {
+                                Command::Emit => builder.branch(var, child_count),
+                                Command::Skip => builder.list(child_count),
+                            };
+                        },
+                    }
+                },
+                Err(error) => {
+                    return Err(ParseError::Syn(tokens, error));
+                }
+            }
+        }
+
+        Ok(builder.build(tokens))
+    }
+}
+
+mod compile;
+mod rewrite;
+
+#[cfg(test)]
+mod tests;
\ No newline at end of file
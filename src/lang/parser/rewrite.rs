@@ -0,0 +1,64 @@
+use crate::lang::lex::{Token, Span};
+
+/// Rule rewriting the tokens between lexing and parsing, such as inserting
+/// the semicolons a language leaves implicit at the ends of lines. Rules are
+/// applied one after the other, each to the tokens left by the one before.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Rewrite {
+    /// Inserts an empty token of `class` after each token of the `after`
+    /// classes that is the last of its line, as with Go's semicolons.
+    Insert { class: usize, after: Vec<usize> },
+    /// Drops tokens of `class` that are followed by a token of the `before`
+    /// classes, or all of them if `before` is empty.
+    Drop { class: usize, before: Vec<usize> },
+    /// Merges a token of `first` directly followed by a token of `second`
+    /// into one token of `into`, as with `>` `>` lexed apart for generics.
+    Merge { first: usize, second: usize, into: usize },
+}
+
+impl Rewrite {
+    /// Applies the rule to `tokens` lexed from `text`.
+    #[must_use]
+    pub fn apply<'a>(&self, text: &'a str, tokens: Vec<Token<'a>>) -> Vec<Token<'a>> {
+        let mut rewritten: Vec<Token> = Vec::with_capacity(tokens.len());
+
+        match self {
+            Rewrite::Insert { class, after } => {
+                for (i, &token) in tokens.iter().enumerate() {
+                    rewritten.push(token);
+
+                    let end = token.end(text);
+                    let ends_line = tokens.get(i + 1).map_or(true, |next| end.line < next.start.line);
+                    if ends_line && after.contains(&token.class) {
+                        let span = Span { start: token.span.end, end: token.span.end };
+                        rewritten.push(Token { lexeme: "", class: *class, span, start: end });
+                    }
+                }
+            },
+            Rewrite::Drop { class, before } => {
+                for (i, &token) in tokens.iter().enumerate() {
+                    let dropped = token.class == *class
+                        && (before.is_empty() || tokens.get(i + 1).map_or(false, |next| before.contains(&next.class)));
+
+                    if !dropped {
+                        rewritten.push(token);
+                    }
+                }
+            },
+            Rewrite::Merge { first, second, into } => {
+                for token in tokens {
+                    match rewritten.last_mut() {
+                        Some(last) if last.class == *first && token.class == *second && last.span.end == token.span.start => {
+                            last.lexeme = &text[last.span.start..token.span.end];
+                            last.class = *into;
+                            last.span.end = token.span.end;
+                        },
+                        _ => rewritten.push(token),
+                    }
+                }
+            },
+        }
+
+        rewritten
+    }
+}
\ No newline at end of file
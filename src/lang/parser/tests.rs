@@ -0,0 +1,82 @@
+use crate::lang::re::{self, RegEx};
+use super::{Parser, Rewrite};
+
+/// Compiles a parser of `;`-separated statements, with the given rewrite
+/// rules between its lexer and parser.
+macro_rules! statements {
+    ($($rewrite:tt)*) => {
+        parser_def! {
+            lexer: {
+                [skip] _ws:      re::any(" \n").plus(),
+                [skip] _comment: re::literal("/*").then(&re::range(' ', ')').or(&re::range('+', '~')).or(&re::literal("\n")).star()).then(&re::literal("*/")),
+                ident:           re::range('a', 'z').plus(),
+                gt:              re::literal(">"),
+                shr:             RegEx::none(),
+                rbrace:          re::literal("}"),
+                semi:            re::literal(";"),
+            },
+            rewrite: { $($rewrite)* },
+            parser: {
+                Program          : Statements,
+                [skip] Statements : Statements Statement
+                                 | Statement,
+                Statement        : ident semi,
+            }
+        }.compile().unwrap()
+    };
+}
+
+fn lexemes<'a>(parser: &'a Parser, text: &'a str) -> Vec<(&'a str, &'a str)> {
+    parser.tokenize(text).unwrap().iter().map(|token| (token.lexeme, parser.lex_labels[token.class].as_str())).collect()
+}
+
+#[test]
+fn insert() {
+    let parser = statements! { insert semi after [ident] };
+
+    // at line ends and the end of the text, but not within lines
+    assert_eq!(lexemes(&parser, "a b\nc"), &[("a", "ident"), ("b", "ident"), ("", "semi"), ("c", "ident"), ("", "semi")]);
+
+    // skipped text spanning lines ends the line, as in Go
+    assert_eq!(lexemes(&parser, "a /* b */ c /* d\n */ e;"), &[("a", "ident"), ("c", "ident"), ("", "semi"), ("e", "ident"), (";", "semi")]);
+
+    let tokens = parser.tokenize("ab \n").unwrap();
+    assert_eq!((tokens[1].span.start, tokens[1].start.column), (2, 3));
+    assert!(parser.cst("a\nb ; c\n").is_ok());
+}
+
+#[test]
+fn drop() {
+    let parser = statements! { drop semi before [rbrace] };
+    assert_eq!(lexemes(&parser, "a; b ;}; c;"), &[("a", "ident"), (";", "semi"), ("b", "ident"), ("}", "rbrace"), (";", "semi"), ("c", "ident"), (";", "semi")]);
+
+    let parser = statements! { drop semi };
+    assert_eq!(lexemes(&parser, "a; b ;}; c;"), &[("a", "ident"), ("b", "ident"), ("}", "rbrace"), ("c", "ident")]);
+}
+
+#[test]
+fn merge() {
+    let parser = statements! { merge gt gt into shr };
+    assert_eq!(lexemes(&parser, "a >> b > > c >>> d"), &[
+        ("a", "ident"), (">>", "shr"), ("b", "ident"), (">", "gt"), (">", "gt"), ("c", "ident"), (">>", "shr"), (">", "gt"), ("d", "ident"),
+    ]);
+}
+
+#[test]
+fn rules_apply_in_order() {
+    let parser = statements! {
+        insert semi after [ident, rbrace],
+        drop semi before [rbrace],
+    };
+    assert_eq!(parser.rewrites, &[
+        Rewrite::Insert { class: 6, after: vec![2, 5] },
+        Rewrite::Drop { class: 6, before: vec![5] },
+    ]);
+    assert_eq!(lexemes(&parser, "a\n}\n"), &[("a", "ident"), ("}", "rbrace"), ("", "semi")]);
+}
+
+#[test]
+#[should_panic(expected = "undefined token class semicolon")]
+fn undefined_class() {
+    let _ = statements! { insert semicolon after [ident] };
+}
\ No newline at end of file
@@ -1,225 +1,148 @@
-use crate::iter::IteratorExtensions;
-use std::iter::once;
-use std::cmp::{min, max, Ordering::{Less, Greater}};
-use std::slice;
-use std::ops::RangeInclusive;
-
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Set of bytes, stored as a 256-bit bitset.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct CharSet {
-    intervals: Vec<(u8, u8)>,
+    bits: [u64; 4],
 }
 
 impl CharSet {
     #[must_use]
     pub fn empty() -> Self {
-        Self { intervals: Vec::new() }
+        Self { bits: [0; 4] }
     }
 
     #[must_use]
     pub fn universe() -> Self {
-        Self { intervals: vec![(u8::MIN, u8::MAX)] }
+        Self { bits: [u64::MAX; 4] }
     }
 
     #[must_use]
     pub fn point(value: u8) -> Self {
-        Self { intervals: vec![(value, value)] }
+        let mut bits = [0; 4];
+        bits[value as usize / 64] = 1 << (value % 64);
+        Self { bits }
     }
 
     #[must_use]
     pub fn range(from: u8, to: u8) -> Self {
-        Self { intervals: vec![if from <= to { (from, to) } else { (to, from) }] }
+        let (from, to) = if from <= to { (from, to) } else { (to, from) };
+        let mut bits = [0; 4];
+        for (i, word) in bits.iter_mut().enumerate() {
+            // bounds of the range relative to this word, clamped to [0, 64)
+            let low  = (from as usize).saturating_sub(64 * i);
+            let high = (to as usize + 1).saturating_sub(64 * i).min(64);
+            if low < high {
+                *word = (u64::MAX >> (64 - (high - low))) << low;
+            }
+        }
+        Self { bits }
     }
 
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.intervals.is_empty()
+        self.bits.iter().all(|&word| word == 0)
     }
 
     #[must_use]
     pub fn is_universe(&self) -> bool {
-        if let Some(&interval) = self.intervals.get(0) {
-            interval.0 == u8::MIN && interval.1 == u8::MAX
-        } else {
-            false
-        }
+        self.bits.iter().all(|&word| word == u64::MAX)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
     }
 
     #[must_use]
     pub fn min(&self) -> Option<u8> {
-        if let Some(interval) = self.intervals.first() {
-            Some(interval.0)
-        } else {
-            None
-        }
+        self.chars().next()
     }
 
     #[must_use]
     pub fn contains(&self, x: u8) -> bool {
-        // Standard binary search. Not using binary_search_by() since
-        // we don't need the index of containing interval.
-
-        let mut size = self.intervals.len();
-        if size == 0 {
-            return false;
-        }
-        let mut base = 0_usize;
-
-        while size > 1 {
-            let half = size / 2;
-            let mid = base + half;
-
-            // this is safe, don't worry
-            let interval = unsafe { *self.intervals.get_unchecked(mid) };
-
-            if x < interval.0 {
-                size -= half;
-            } else if x > interval.1 {
-                size -= half;
-                base = mid;
-            } else {
-                return true;
-            }
-        }
-
-        // also safe
-        let interval = unsafe { *self.intervals.get_unchecked(base) };
-
-        interval.0 <= x && x <= interval.1
+        self.bits[x as usize / 64] & (1 << (x % 64)) != 0
     }
 
     #[must_use]
     pub fn complement(&self) -> Self {
-        let intervals = match *self.intervals {
-            [(low, _), .., (_, high)] => {
-                let iter = self.intervals[..self.intervals.len()].iter().zip(&self.intervals[1..]).map(|((_, a), (b, _))| (a + 1, b - 1));
-                match (low, high) {
-                    (u8::MIN, u8::MAX) => { iter.collect() },
-                    (u8::MIN, high)    => { iter.chain(once((high + 1, u8::MAX))).collect() },
-                    (low, u8::MAX)     => { once((u8::MIN, low - 1)).chain(iter).collect() },
-                    (low, high)        => { once((u8::MIN, low - 1)).chain(iter).chain(once((high + 1, u8::MAX))).collect() },
-                }
-            },
-            [value] => {
-                match value {
-                    (u8::MIN, u8::MAX) => { Vec::new() },
-                    (u8::MIN, high)    => { vec![(high + 1, u8::MAX)] },
-                    (low, u8::MAX)     => { vec![(u8::MIN, low - 1)] },
-                    (low, high)        => { vec![(u8::MIN, low - 1), (high + 1, u8::MAX)] },
-                }
-            },
-            [] =>  {
-                vec![(u8::MIN, u8::MAX)]
-            },
-        };
-
-        Self { intervals }
+        let mut bits = self.bits;
+        for word in &mut bits {
+            *word = !*word;
+        }
+        Self { bits }
     }
 
     #[must_use]
     pub fn intersection(&self, other: &Self) -> Self {
-        let mut intervals = Vec::new();
-        
-        let mut iter1 = self.intervals.iter();
-        let mut iter2 = other.intervals.iter();
-        
-        let mut a = if let Some(interval) = iter1.next() { *interval } else { return Self { intervals } };
-        let mut b = if let Some(interval) = iter2.next() { *interval } else { return Self { intervals } };
-
-        loop {
-            let low = max(a.0, b.0);
-            let high = min(a.1, b.1);
-
-            if low <= high {
-                intervals.push((low, high));
-            }
-
-            if a.1 < b.1 {
-                a = if let Some(interval) = iter1.next() { *interval } else { return Self { intervals } };
-            } else {
-                b = if let Some(interval) = iter2.next() { *interval } else { return Self { intervals } };
-            }
+        let mut bits = self.bits;
+        for (word, other) in bits.iter_mut().zip(&other.bits) {
+            *word &= other;
         }
+        Self { bits }
     }
 
     #[must_use]
     pub fn union(&self, other: &Self) -> Self {
-        // TODO: refactor. Using merge_by() results in more comparisons that necessary.
-
-        let iter1 = self.intervals.iter().cloned();
-        let iter2 = other.intervals.iter().cloned();
-
-        let mut iter = iter1.merge_by(iter2, |i1, i2| {
-            if i1.0 <= i2.0 { Less } else { Greater }
-        });
-
-        let mut intervals = Vec::new();
-
-        if let Some(mut interval) = iter.next() {
-            for next_interval in iter {
-                if interval.1 >= next_interval.0 || next_interval.0 - interval.1 == 1 {
-                    interval.1 = max(interval.1, next_interval.1);
-                } else {
-                    intervals.push(interval);
-                    interval = next_interval;
-                }
-            }
-
-            intervals.push(interval);
+        let mut bits = self.bits;
+        for (word, other) in bits.iter_mut().zip(&other.bits) {
+            *word |= other;
         }
-
-        Self { intervals }
+        Self { bits }
     }
 
     #[must_use]
     pub fn chars(&self) -> Chars {
-        Chars::new(self)
+        Chars { bits: self.bits, word: 0 }
     }
-}
 
-impl std::fmt::Debug for CharSet {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        if self.intervals.is_empty() {
-            f.write_str("{}")
-        } else {
-            f.write_str("{")?;
-            f.write_str(&self.intervals.iter().map(|i| {
-                if i.0 == i.1 {
-                    format!("{:02x}", i.0)
-                } else {
-                    format!("[{:02x}..{:02x}]", i.0, i.1)
-                }
-            }).collect::<Vec<_>>().join(","))?;
-            f.write_str("}")
+    /// Returns the set as a sorted list of disjoint, non-adjacent, inclusive intervals.
+    #[must_use]
+    pub fn intervals(&self) -> Vec<(u8, u8)> {
+        let mut intervals: Vec<(u8, u8)> = Vec::new();
+        for x in self.chars() {
+            match intervals.last_mut() {
+                Some(interval) if interval.1 + 1 == x => interval.1 = x,
+                _ => intervals.push((x, x)),
+            }
         }
+        intervals
     }
 }
 
-pub struct Chars<'a> {
-    interval_iter: slice::Iter<'a, (u8, u8)>,
-    range: RangeInclusive<u8>,
+impl std::fmt::Debug for CharSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.write_str("{")?;
+        f.write_str(&self.intervals().iter().map(|i| {
+            if i.0 == i.1 {
+                format!("{:02x}", i.0)
+            } else {
+                format!("[{:02x}..{:02x}]", i.0, i.1)
+            }
+        }).collect::<Vec<_>>().join(","))?;
+        f.write_str("}")
+    }
 }
 
-impl<'a> Chars<'a> {
-    fn new(set: &'a CharSet) -> Self {
-        Self {
-            interval_iter: set.intervals.iter(),
-            range: RangeInclusive::new(1, 0), // starts range as done
-        }
-    }
+pub struct Chars {
+    bits: [u64; 4],
+    word: usize,
 }
 
-impl Iterator for Chars<'_> {
+impl Iterator for Chars {
     type Item = u8;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.range.next().or_else(|| {
-            if let Some(&(start, end)) = self.interval_iter.next() {
-                self.range = RangeInclusive::new(start, end);
-                self.range.next()
+        while self.word < 4 {
+            let bits = &mut self.bits[self.word];
+            if *bits == 0 {
+                self.word += 1;
             } else {
-                None
+                let offset = bits.trailing_zeros() as usize;
+                *bits &= *bits - 1; // clear lowest set bit
+                #[allow(clippy::cast_possible_truncation)]
+                return Some((64 * self.word + offset) as u8);
             }
-        })
+        }
+        None
     }
 }
 
@@ -254,9 +177,9 @@ mod tests {
         let set1 = CharSet::range(60, 180);
         let set2 = set1.complement();
 
-        assert_eq!(set2.intervals, &[(0, 59), (181, 255)]);
-        assert_eq!(set2.intersection(&set1).intervals, &[]);
-        assert_eq!(CharSet::empty().intervals, CharSet::range(0, 255).complement().intervals)
+        assert_eq!(set2.intervals(), &[(0, 59), (181, 255)]);
+        assert_eq!(set2.intersection(&set1).intervals(), &[]);
+        assert_eq!(CharSet::empty().intervals(), CharSet::range(0, 255).complement().intervals())
     }
 
     #[test]
@@ -267,7 +190,21 @@ mod tests {
 
         let union = set1.union(&set2).union(&set3);
 
-        assert_eq!(union.intervals, &[(10, 20), (60, 200)]);
+        assert_eq!(union.intervals(), &[(10, 20), (60, 200)]);
+    }
+
+    #[test]
+    fn range() {
+        assert_eq!(CharSet::range(0, 255), CharSet::universe());
+        assert_eq!(CharSet::range(70, 50).intervals(), &[(50, 70)]);
+        assert_eq!(CharSet::range(60, 200).intervals(), &[(60, 200)]);
+        assert_eq!(CharSet::range(64, 127).len(), 64);
+        assert_eq!(CharSet::range(63, 128).chars().next(), Some(63));
+
+        for x in 0..=255 {
+            assert!(CharSet::point(x).contains(x));
+            assert_eq!(CharSet::point(x).intervals(), &[(x, x)]);
+        }
     }
 
     #[test]
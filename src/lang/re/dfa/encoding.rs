@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use super::{DFA, DFAState};
+
+/// Encoding of the input consumed by a `DFA`. A `RegEx` always describes
+/// UTF-8 encoded text, but its `DFA` can be lowered to consume other
+/// encodings of the same characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    /// Code units are consumed as two bytes, low byte first.
+    Utf16,
+    /// ISO-8859-1, i.e. each byte is the code point of a char.
+    Latin1,
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub fn encode(dfa: &DFA, encoding: Encoding) -> DFA {
+    let mut encoder = Encoder {
+        dfa,
+        table: dense_table(dfa),
+        states: vec![DFAState::sink()],
+        mains: HashMap::new(),
+        highs: HashMap::new(),
+        rows: HashMap::new(),
+        pending: Vec::new(),
+    };
+
+    // s1 = start state
+    encoder.main(1);
+
+    while let Some(item) = encoder.pending.pop() {
+        match (item, encoding) {
+            (Pending::Main { source, id }, Encoding::Utf8) => {
+                for byte in 0..=u8::MAX {
+                    let dest = encoder.main(encoder.run(source, &[byte]));
+                    encoder.link(id, byte, dest);
+                }
+            },
+            (Pending::Main { source, id }, Encoding::Latin1) => {
+                for byte in 0..=u8::MAX {
+                    let mut buffer = [0; 4];
+                    let dest = encoder.main(encoder.run(source, char::from(byte).encode_utf8(&mut buffer).as_bytes()));
+                    encoder.link(id, byte, dest);
+                }
+            },
+            (Pending::Main { source, id }, Encoding::Utf16) => {
+                for low in 0..=u8::MAX {
+                    let row = (0..=u8::MAX).map(|high| {
+                        let unit = u16::from_le_bytes([low, high]);
+                        match unit {
+                            0xD800..=0xDBFF => {
+                                // Leading surrogate determines the first two bytes of
+                                // the UTF-8 encoding and two bits of the third.
+                                let top = u32::from(unit - 0xD800) + 0x40; // code point >> 10
+                                let prefix = [0xF0 | (top >> 8) as u8, 0x80 | ((top >> 2) & 0x3F) as u8];
+                                encoder.high(encoder.run(source, &prefix), (top & 0x3) as u8)
+                            },
+                            0xDC00..=0xDFFF => 0, // unpaired trailing surrogate
+                            _ => {
+                                let c = char::from_u32(u32::from(unit)).unwrap();
+                                let mut buffer = [0; 4];
+                                encoder.main(encoder.run(source, c.encode_utf8(&mut buffer).as_bytes()))
+                            },
+                        }
+                    }).collect();
+                    let dest = encoder.row(row);
+                    encoder.link(id, low, dest);
+                }
+            },
+            (Pending::High { source, bits, id }, _) => {
+                for low in 0..=u8::MAX {
+                    let row = (0..=u8::MAX).map(|high| {
+                        let unit = u16::from_le_bytes([low, high]);
+                        if let 0xDC00..=0xDFFF = unit {
+                            let unit = unit - 0xDC00;
+                            let suffix = [0x80 | (bits << 4) | (unit >> 6) as u8, 0x80 | (unit & 0x3F) as u8];
+                            encoder.main(encoder.run(source, &suffix))
+                        } else {
+                            0 // leading surrogate must be followed by a trailing surrogate
+                        }
+                    }).collect();
+                    let dest = encoder.row(row);
+                    encoder.link(id, low, dest);
+                }
+            },
+        }
+    }
+
+    DFA { states: encoder.states, prefix: Vec::new() }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+enum Pending {
+    /// State equivalent to a state of the source `DFA`.
+    Main { source: usize, id: usize },
+    /// State after a UTF-16 leading surrogate, where `source` is the state of
+    /// the source `DFA` after the first two bytes of the UTF-8 encoding, and
+    /// `bits` are the high bits of the third byte.
+    High { source: usize, bits: u8, id: usize },
+}
+
+struct Encoder<'a> {
+    dfa: &'a DFA,
+    table: Vec<usize>,
+    states: Vec<DFAState>,
+    mains: HashMap<usize, usize>,
+    highs: HashMap<(usize, u8), usize>,
+    rows: HashMap<Vec<usize>, usize>, // states in the middle of a UTF-16 code unit
+    pending: Vec<Pending>,
+}
+
+impl Encoder<'_> {
+    /// Runs the source `DFA` on `bytes` from state `source`.
+    fn run(&self, source: usize, bytes: &[u8]) -> usize {
+        bytes.iter().fold(source, |id, &byte| self.table[256 * id + byte as usize])
+    }
+
+    fn link(&mut self, id: usize, symbol: u8, dest: usize) {
+        if dest != 0 {
+            self.states[id].next.insert(symbol, dest);
+        }
+    }
+
+    fn main(&mut self, source: usize) -> usize {
+        if source == 0 {
+            return 0;
+        }
+
+        if let Some(&id) = self.mains.get(&source) {
+            id
+        } else {
+            let id = self.add_state(self.dfa.states[source].classes.clone());
+            self.mains.insert(source, id);
+            self.pending.push(Pending::Main { source, id });
+            id
+        }
+    }
+
+    fn high(&mut self, source: usize, bits: u8) -> usize {
+        if source == 0 {
+            return 0;
+        }
+
+        if let Some(&id) = self.highs.get(&(source, bits)) {
+            id
+        } else {
+            let id = self.add_state(Vec::new());
+            self.highs.insert((source, bits), id);
+            self.pending.push(Pending::High { source, bits, id });
+            id
+        }
+    }
+
+    /// Returns a state that transitions to `row[symbol]` on each symbol.
+    fn row(&mut self, row: Vec<usize>) -> usize {
+        if row.iter().all(|&dest| dest == 0) {
+            return 0;
+        }
+
+        if let Some(&id) = self.rows.get(&row) {
+            id
+        } else {
+            let id = self.add_state(Vec::new());
+            for (symbol, &dest) in (0..=u8::MAX).zip(&row) {
+                self.link(id, symbol, dest);
+            }
+            self.rows.insert(row, id);
+            id
+        }
+    }
+
+    fn add_state(&mut self, classes: Vec<usize>) -> usize {
+        self.states.push(DFAState::new(HashMap::new(), classes));
+        self.states.len() - 1
+    }
+}
+
+fn dense_table(dfa: &DFA) -> Vec<usize> {
+    let mut table = vec![0; 256 * dfa.states.len()];
+    for (id, state) in dfa.states.iter().enumerate() {
+        for (&symbol, &dest) in &state.next {
+            table[256 * id + symbol as usize] = dest;
+        }
+    }
+    table
+}
@@ -29,10 +29,12 @@ pub fn minimize(dfa: &DFA) -> DFA {
             }
         }
 
-        states.push(DFAState::new(next, set.iter().filter_map(|&id| dfa.states[id].class).min()));
+        // states of an equivalence class share the same accepting classes
+        let first = *set.iter().next().unwrap();
+        states.push(DFAState::new(next, dfa.states[first].classes.clone()));
     }
 
-    DFA { states }
+    DFA { states, prefix: dfa.prefix.clone() }
 }
 
 // =================
@@ -139,10 +141,9 @@ fn alphabet(states: &[DFAState]) -> Vec<u8> {
 /// algorithm is to repeatedly refine the paritioning such that each set
 /// strictly contains equivalent nodes.
 fn coarse_partition(dfa: &DFA) -> Partition {
-    let mut partition: HashMap<_, Ids> = HashMap::new();
+    let mut partition: HashMap<&[usize], Ids> = HashMap::new();
     for (id, state) in dfa.states.iter().enumerate() {
-        let class = state.class.map_or(0, |class| class + 1);
-        partition.entry(class).or_default().insert(id);
+        partition.entry(&state.classes).or_default().insert(id);
     }
     partition.values().cloned().collect()
 }
@@ -1,20 +1,34 @@
 use std::ops::Deref;
 use std::collections::{HashSet, HashMap, BTreeMap};
 use std::iter::once;
+use std::ops::Range;
 
 use super::{CharSet, RegEx, RENode, RENodeRef};
 use crate::debug::StringBuilder;
 
 pub struct DFA {
     states: Vec<DFAState>,
+    prefix: Vec<u8>, // literal that every accepted input starts with
 }
 
 pub struct DFAState {
-    pub class: Option<usize>,
+    /// All accepting classes in ascending order.
+    pub classes: Vec<usize>,
     pub next: HashMap<u8, usize>,
     _private: (),
 }
 
+/// Describes how far a prefix of some input got through a `DFA`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefixStatus {
+    /// No extension of the prefix can ever be accepted.
+    Dead,
+    /// The prefix is not accepted, but some extension of it is.
+    Live,
+    /// The prefix is accepted as the contained class.
+    Accepting(usize),
+}
+
 impl From<&RegEx> for DFA {
     fn from(regex: &RegEx) -> Self {
         DFABuilder::build(&RENodeRefVec::new(vec![regex.root.clone()]))
@@ -37,12 +51,63 @@ impl DFA {
         hopcroft::minimize(self)
     }
 
+    /// Constructs the equivalent DFA that consumes input in another encoding.
+    #[must_use]
+    pub fn encode(&self, encoding: Encoding) -> Self {
+        encoding::encode(self, encoding)
+    }
+
     #[must_use]
     pub fn matches(&self, text: &str) -> bool {
         // Note: start index is always 1.
         self.class(text.bytes().fold(1, |id, byte| { self.step(id, byte) })).is_some()
     }
 
+    /// Classifies `text` as a prefix of the input: whether it is accepted,
+    /// could still be extended into an accepted input, or is dead.
+    #[must_use]
+    pub fn prefix_status(&self, text: &str) -> PrefixStatus {
+        // Note: start index is always 1.
+        let mut id = 1;
+
+        for byte in text.bytes() {
+            id = self.step(id, byte);
+            if id == 0 {
+                return PrefixStatus::Dead;
+            }
+        }
+
+        if let Some(class) = self.class(id) {
+            PrefixStatus::Accepting(class)
+        } else if self.is_live(id) {
+            PrefixStatus::Live
+        } else {
+            PrefixStatus::Dead
+        }
+    }
+
+    /// Returns every class that accepts `text`, in ascending order.
+    #[must_use]
+    pub fn matching_classes(&self, text: &str) -> &[usize] {
+        // Note: start index is always 1.
+        self.classes(text.bytes().fold(1, |id, byte| { self.step(id, byte) }))
+    }
+
+    /// Finds the leftmost-longest substring of `text` accepted by the `DFA`,
+    /// in a single pass over `text`. Positions that cannot start a match are
+    /// skipped by searching for the literal prefix shared by all accepted
+    /// inputs, if there is one.
+    #[must_use]
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        self.search(text.as_bytes(), &mut 0)
+    }
+
+    /// Literal that every input accepted by the `DFA` starts with.
+    #[must_use]
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
     #[must_use]
     pub fn step(&self, id: usize, symbol: u8) -> usize {
         match self.states[id].next.get(&symbol) {
@@ -53,7 +118,12 @@ impl DFA {
 
     #[must_use]
     pub fn class(&self, id: usize) -> Option<usize> {
-        self.states[id].class
+        self.states[id].class()
+    }
+
+    #[must_use]
+    pub fn classes(&self, id: usize) -> &[usize] {
+        &self.states[id].classes
     }
 
     #[must_use]
@@ -77,15 +147,16 @@ impl DFA {
 
         obj.writeln("node[shape=doublecircle];");
         for (a, state) in self.states().iter().enumerate().skip(1) {
-            if let Some(class) = state.class {
-                obj.writeln(&format!("s{}[label=\"{}\"];", a, class));
+            if !state.classes.is_empty() {
+                let classes = state.classes.iter().map(ToString::to_string).collect::<Vec<_>>();
+                obj.writeln(&format!("s{}[label=\"{}\"];", a, classes.join(",")));
             }
         }
         obj.newline();
 
         obj.writeln("node[shape=circle];");
         for (a, state) in self.states().iter().enumerate().skip(1) {
-            if state.class.is_none() {
+            if state.class().is_none() {
                 obj.writeln(&format!("s{}[label=\"\"];", a));
             }
         }
@@ -116,21 +187,119 @@ impl DFA {
     }
 }
 
+impl DFAState {
+    /// Returns the lowest-numbered accepting class, used to prioritise
+    /// overlapping classes.
+    #[must_use]
+    pub fn class(&self) -> Option<usize> {
+        self.classes.first().copied()
+    }
+}
+
 // =================
 // === INTERNALS ===
 // =================
 
+impl DFA {
+    /// Finds the leftmost-longest match in `bytes` as in `find`, adding the
+    /// number of steps taken to `steps`. Runs of the `DFA` from every possible
+    /// start advance together, one byte at a time. Runs that land in the same
+    /// state accept the same continuations, so only the leftmost of them is
+    /// kept, and each byte is stepped at most once per state.
+    fn search(&self, bytes: &[u8], steps: &mut usize) -> Option<Range<usize>> {
+        let mut runs: Vec<(usize, usize)> = Vec::new(); // states and starts of live runs, leftmost first
+        let mut next_runs = Vec::new();
+        let mut reached = vec![usize::MAX; self.states.len()]; // index at which each state was last reached
+        let mut candidate = prefilter::find(bytes, &self.prefix); // next index that can start a match
+        let mut found: Option<Range<usize>> = None;
+        let mut index = 0;
+
+        loop {
+            // no match can start right of one already found
+            if found.is_none() && candidate == Some(index) {
+                // Note: start index is always 1.
+                if reached[1] != index {
+                    reached[1] = index;
+                    runs.push((1, index));
+                }
+                candidate = if index < bytes.len() {
+                    prefilter::find(&bytes[index + 1..], &self.prefix).map(|offset| index + 1 + offset)
+                } else {
+                    None
+                };
+            }
+
+            // the leftmost accepting run wins, even over runs accepted before
+            if let Some(&(_, start)) = runs.iter().find(|&&(id, _)| self.class(id).is_some()) {
+                if found.as_ref().map_or(true, |found| start <= found.start) {
+                    found = Some(start..index);
+                }
+            }
+            if let Some(found) = &found {
+                runs.retain(|&(_, start)| start <= found.start);
+            }
+
+            if runs.is_empty() {
+                match (&found, candidate) {
+                    (None, Some(candidate)) => {
+                        index = candidate;
+                        continue;
+                    },
+                    _ => return found,
+                }
+            }
+            if index == bytes.len() {
+                return found;
+            }
+
+            next_runs.clear();
+            for &(id, start) in &runs {
+                *steps += 1;
+                let next_id = self.step(id, bytes[index]);
+                if next_id != 0 && reached[next_id] != index + 1 {
+                    reached[next_id] = index + 1;
+                    next_runs.push((next_id, start));
+                }
+            }
+            std::mem::swap(&mut runs, &mut next_runs);
+            index += 1;
+        }
+    }
+
+    /// Checks if some accept state is reachable from state `id`. Only the
+    /// sink is guaranteed to be dead in an unminimized `DFA`, so other
+    /// states are checked by search.
+    fn is_live(&self, id: usize) -> bool {
+        let mut visited = vec![false; self.states.len()];
+        let mut stack = vec![id];
+
+        while let Some(id) = stack.pop() {
+            if self.states[id].class().is_some() {
+                return true;
+            }
+            for &next_id in self.states[id].next.values() {
+                if !visited[next_id] {
+                    visited[next_id] = true;
+                    stack.push(next_id);
+                }
+            }
+        }
+
+        false
+    }
+}
+
 impl DFAState {
-    fn new(next: HashMap<u8, usize>, class: Option<usize>) -> Self {
+    fn new(next: HashMap<u8, usize>, classes: Vec<usize>) -> Self {
         Self {
-            class,
+            classes,
             next,
             _private: (),
         }
     }
 
     fn sink() -> Self {
-        Self::new(HashMap::new(), None)
+        Self::new(HashMap::new(), Vec::new())
     }
 }
 
@@ -160,8 +329,8 @@ impl RENodeRefVec {
         Self { vec: self.vec.iter().map(|node| node.deriv(a)).collect() }
     }
 
-    fn class(&self) -> Option<usize> {
-        self.vec.iter().position(|node| node.is_nullable())
+    fn classes(&self) -> Vec<usize> {
+        self.vec.iter().enumerate().filter(|(_, node)| node.is_nullable()).map(|(i, _)| i).collect()
     }
 }
 
@@ -185,13 +354,14 @@ impl DFABuilder {
 
         DFA {
             states: builder.states,
+            prefix: prefilter::common_prefix(start.iter().map(prefilter::literal_prefix)),
         }
     }
 
     fn add_state(&mut self, q: &RENodeRefVec) -> usize {
         let idx = self.states.len();
         self.re2idx.insert(q.clone(), idx);
-        self.states.push(DFAState::new(HashMap::new(), q.class()));
+        self.states.push(DFAState::new(HashMap::new(), q.classes()));
         idx
     }
 
@@ -241,8 +411,7 @@ fn approx_deriv_classes(root: &RENodeRef) -> HashSet<CharSet> {
             RENode::Set(set) => {
                 // TODO: set cannot be empty?
                 if !set.is_empty() && !set.is_universe() {
-                    let cset = &set.complement();
-                    charsets = cross(&charsets, vec![set, cset].into_iter());
+                    charsets = cross(&charsets, &[*set, set.complement()]);
                 }
             },
             RENode::Cat(children) => {
@@ -275,7 +444,11 @@ fn approx_deriv_classes_vec(root: &RENodeRefVec) -> HashSet<CharSet> {
     })
 }
 
+mod encoding;
 mod hopcroft;
+mod prefilter;
+
+pub use self::encoding::Encoding;
 
 #[cfg(test)]
 mod tests;
\ No newline at end of file
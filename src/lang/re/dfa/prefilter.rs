@@ -0,0 +1,87 @@
+use super::{RENode, RENodeRef};
+
+/// Computes a literal string that every string recognized by `root` must
+/// start with. The result is not necessarily the longest such prefix, since
+/// only the structure of the `RegEx` is inspected.
+pub fn literal_prefix(root: &RENodeRef) -> Vec<u8> {
+    match root.as_ref() {
+        RENode::None | RENode::Epsilon | RENode::Star(_) | RENode::Not(_) => {
+            Vec::new()
+        },
+        RENode::Set(_) => {
+            literal(root).unwrap_or_default()
+        },
+        RENode::Cat(children) => {
+            let mut prefix = Vec::new();
+            for child in children {
+                if let Some(word) = literal(child) {
+                    prefix.extend(word);
+                } else {
+                    prefix.extend(literal_prefix(child));
+                    break;
+                }
+            }
+            prefix
+        },
+        RENode::Or(children) => {
+            common_prefix(children.iter().map(literal_prefix))
+        },
+        RENode::And(children) => {
+            // All prefixes must agree for the intersection to be non-empty,
+            // so the longest one is required.
+            children.iter().map(literal_prefix).max_by_key(Vec::len).unwrap_or_default()
+        },
+    }
+}
+
+/// Computes the longest common prefix of several literals.
+pub fn common_prefix<I: IntoIterator<Item = Vec<u8>>>(prefixes: I) -> Vec<u8> {
+    let mut iter = prefixes.into_iter();
+    let first = iter.next().unwrap_or_default();
+    iter.fold(first, |mut prefix, other| {
+        let len = prefix.iter().zip(&other).take_while(|(a, b)| a == b).count();
+        prefix.truncate(len);
+        prefix
+    })
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack`.
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+
+    let mut offset = 0;
+    while let Some(i) = haystack[offset..].iter().position(|&byte| byte == needle[0]) {
+        let start = offset + i;
+        if haystack[start..].starts_with(needle) {
+            return Some(start);
+        }
+        offset = start + 1;
+    }
+
+    None
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+/// Returns the only string recognized by `node`, if there is exactly one.
+fn literal(node: &RENodeRef) -> Option<Vec<u8>> {
+    match node.as_ref() {
+        RENode::Epsilon => {
+            Some(Vec::new())
+        },
+        RENode::Set(set) => {
+            if set.len() == 1 { Some(vec![set.min()?]) } else { None }
+        },
+        RENode::Cat(children) => {
+            children.iter().try_fold(Vec::new(), |mut word, child| {
+                word.extend(literal(child)?);
+                Some(word)
+            })
+        },
+        _ => None,
+    }
+}
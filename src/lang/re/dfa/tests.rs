@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
-use super::{RegEx, DFA, CharSet};
+use super::{RegEx, DFA, Encoding, PrefixStatus, CharSet};
+use crate::lang::re;
 
 #[test]
 fn test() {
@@ -44,4 +45,125 @@ fn indentifiers() {
     assert!( A.matches("ThIsIsAlLoWeD") );
     assert!( A.matches("__allowed_123_") );
     assert!( !A.matches("not allowed") );
+}
+
+#[test]
+fn prefix_status() {
+    let digit  = RegEx::set(CharSet::range(0x30, 0x39));
+    let hyphen = RegEx::set(CharSet::point(0x2d));
+    let date   = digit.then(&digit).then(&hyphen).then(&digit).then(&digit);
+
+    let A = DFA::from(&date);
+
+    assert_eq!( A.prefix_status(""), PrefixStatus::Live );
+    assert_eq!( A.prefix_status("12-"), PrefixStatus::Live );
+    assert_eq!( A.prefix_status("12-34"), PrefixStatus::Accepting(0) );
+    assert_eq!( A.prefix_status("12-345"), PrefixStatus::Dead );
+    assert_eq!( A.prefix_status("1a"), PrefixStatus::Dead );
+}
+
+#[test]
+fn matching_classes() {
+    let lowercase  = RegEx::set(CharSet::range(0x61, 0x7a));
+    let keyword    = RegEx::set(CharSet::point(0x69)).then(&RegEx::set(CharSet::point(0x66)));
+    let identifier = lowercase.plus();
+
+    let regexes = vec![keyword, identifier];
+
+    for A in &[DFA::from(&regexes), DFA::from(&regexes).minimize()] {
+        assert_eq!( A.matching_classes("if"), &[0, 1] );
+        assert_eq!( A.matching_classes("iff"), &[1] );
+        assert_eq!( A.matching_classes("i"), &[1] );
+        assert!( A.matching_classes("1").is_empty() );
+    }
+}
+
+#[test]
+fn find() {
+    let literal = |s: &str| s.bytes().fold(RegEx::empty(), |r, byte| r.then(&RegEx::set(CharSet::point(byte))));
+    let digit   = RegEx::set(CharSet::range(0x30, 0x39));
+    let error   = literal("ERROR: ").then(&digit.plus());
+    let warning = literal("ERROR: ").then(&literal("warn")).or(&literal("ERR!"));
+
+    let A = DFA::from(&error);
+    let B = DFA::from(&warning).minimize();
+
+    assert_eq!( A.prefix(), b"ERROR: " );
+    assert_eq!( B.prefix(), b"ERR" );
+
+    let text = "INFO: 1\nERROR: x\nERROR: 404\n";
+
+    assert_eq!( A.find(text), Some(17..27) );
+    assert_eq!( A.find(&text[..25]), Some(17..25) );
+    assert_eq!( A.find(&text[..17]), None );
+    assert_eq!( B.find("ERROR: warning"), Some(0..11) );
+    assert_eq!( B.find("ERROR: 42 ERR!"), Some(10..14) );
+
+    // matches starting further left win, even if they end later
+    let C = DFA::from(&literal("abcd").or(&literal("c")));
+    assert_eq!( C.find("xabcd"), Some(1..5) );
+    assert_eq!( C.find("xabcx"), Some(3..4) );
+}
+
+#[test]
+fn linear_find() {
+    let regex = re::literal("ERROR: ").then(&re::range(' ', '~').star()).then(&re::literal("\x01"));
+    let A = DFA::from(&regex).minimize();
+
+    // without merging runs, each prefix restarts a scan to the end of the text
+    let n = 4000;
+    let mut text = "ERROR: x ".repeat(n);
+
+    let mut steps = 0;
+    assert_eq!( A.search(text.as_bytes(), &mut steps), None );
+    assert!( steps <= 2 * text.len(), "stepped {} times over {} bytes", steps, text.len() );
+
+    text.push('\x01');
+    assert_eq!( A.find(&text), Some(0..text.len()) );
+}
+
+#[test]
+fn encodings() {
+    let regex = re::range('a', 'z')
+        .or(&re::range('\u{A0}', '\u{D7FF}'))
+        .or(&re::range('\u{1F600}', '\u{1F64F}'))
+        .plus();
+
+    let A = DFA::from(&regex).minimize();
+    let B = A.encode(Encoding::Latin1).minimize();
+    let C = A.encode(Encoding::Utf16).minimize();
+
+    let run = |dfa: &DFA, bytes: &[u8]| dfa.class(bytes.iter().fold(1, |id, &byte| dfa.step(id, byte))).is_some();
+    let utf16 = |text: &str| text.encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<_>>();
+
+    let samples = ['a', 'z', 'A', '\u{9F}', '\u{A0}', '\u{FF}', '\u{100}', '\u{D7FF}', '\u{E000}', '\u{1F5FF}', '\u{1F600}', '\u{1F64F}', '\u{1F650}'];
+
+    for &c in &samples {
+        for text in &[c.to_string(), format!("ab{}", c), format!("{}{}", c, c)] {
+            assert_eq!( run(&C, &utf16(text)), A.matches(text), "{:?}", text );
+            if c <= '\u{FF}' {
+                let latin1 = text.chars().map(|c| c as u8).collect::<Vec<_>>();
+                assert_eq!( run(&B, &latin1), A.matches(text), "{:?}", text );
+            }
+        }
+    }
+
+    // unpaired surrogates are rejected
+    assert!( !run(&C, &[0x3D, 0xD8]) );
+    assert!( !run(&C, &[0x00, 0xDE]) );
+    assert!( !run(&C, &[0x3D, 0xD8, 0x61, 0x00]) );
+}
+
+#[test]
+fn any_chars() {
+    let A = DFA::from(&re::any("a\u{E9}\u{1F600}")).minimize();
+
+    assert!( A.matches("a") );
+    assert!( A.matches("\u{E9}") );
+    assert!( A.matches("\u{1F600}") );
+    assert!( !A.matches("") );
+    assert!( !A.matches("aa") );
+
+    // no chars to choose from matches nothing, not even the empty string
+    assert!( !DFA::from(&re::any("")).matches("") );
 }
\ No newline at end of file
@@ -7,7 +7,7 @@ use self::char_set::CharSet;
 use self::regex::{RENode, RENodeRef};
 
 pub use self::regex::RegEx;
-pub use self::dfa::DFA;
+pub use self::dfa::{DFA, Encoding, PrefixStatus};
 pub use self::unicode::{basic_latin, basic_multilingual_plane, non_compatibility_char};
 
 /// Constructs a `RegEx` that recognizes some input string only.
@@ -21,55 +21,70 @@ pub fn literal(s: &str) -> RegEx {
 /// Constructs a `RegEx` that recognizes any char in a string.
 #[must_use]
 pub fn any(s: &str) -> RegEx {
-    s.chars().fold(RegEx::empty(), |r, c| {
+    s.chars().fold(RegEx::none(), |r, c| {
         let mut buffer: [u8; 4] = [0; 4];
         r.or(&literal(c.encode_utf8(&mut buffer)))
     })
 }
 
 /// Constructs a `RegEx` that recognizes all chars within a provided range (inclusive).
-/// Also accounts for char ranges that span different number of bytes.
+/// The range is encoded in UTF-8, so chars that span different numbers of bytes are
+/// split into several sequences of byte ranges.
 #[must_use]
 pub fn range(from: char, to: char) -> RegEx {
-    #[allow(clippy::cast_possible_truncation)]
-    fn range32(from: u32, to: u32, optional: bool) -> RegEx {
-        fn byte_range(from: u8, to: u8) -> RegEx {
-            RegEx::set(CharSet::range(from, to))
-        }
-    
-        let (a_low, a_high) = (from as u8 + optional as u8, from >> 8);
-        let (b_low, b_high) = (to as u8, to >> 8);
-    
-        let regex = {
-            if b_high == 0 {
-                byte_range(a_low, b_low)
-            } else if a_high == b_high {
-                byte_range(a_low, b_low).then(&range32(a_high, b_high, false))
-            } else if a_low == u8::MIN && b_low == u8::MAX {
-                byte_range(u8::MIN, u8::MAX).then(&range32(a_high, b_high, a_high == 0))
-            } else if a_low == u8::MIN {
-                byte_range(u8::MIN, b_low).then(&range32(a_high, b_high, a_high == 0))
-                .or(&byte_range(b_low + 1, u8::MAX).then(&range32(a_high, b_high - 1, a_high == 0)))
-            } else if b_low == u8::MAX {
-                byte_range(u8::MIN, a_low - 1).then(&range32(a_high + 1, b_high, false))
-                .or(&byte_range(a_low, u8::MAX).then(&range32(a_high, b_high, a_high == 0)))
-            } else if b_low >= a_low {
-                byte_range(u8::MIN, a_low - 1).then(&range32(a_high + 1, b_high, false))
-                .or(&byte_range(a_low, b_low).then(&range32(a_high, b_high, a_high == 0)))
-                .or(&byte_range(b_low + 1, u8::MAX).then(&range32(a_high, b_high - 1, a_high == 0)))
-            } else if b_high > a_high + 1 && b_low + 1 < a_low {
-                byte_range(u8::MIN, b_low).then(&range32(a_high + 1, b_high, false))
-                .or(&byte_range(b_low + 1, a_low - 1).then(&range32(a_high + 1, b_high - 1, false)))
-                .or(&byte_range(a_low, u8::MAX).then(&range32(a_high, b_high - 1, a_high == 0)))
-            } else {
-                byte_range(u8::MIN, b_low).then(&range32(a_high + 1, b_high, false))
-                .or(&byte_range(a_low, u8::MAX).then(&range32(a_high, b_high - 1, a_high == 0)))
+    let (from, to) = if from <= to { (from, to) } else { (to, from) };
+
+    let mut regex = RegEx::none();
+    let mut stack = vec![(from as u32, to as u32)];
+
+    while let Some((start, mut end)) = stack.pop() {
+        'split: loop {
+            // surrogates are not chars, so are never encoded
+            if start < 0xE000 && end > 0xD7FF {
+                stack.push((0xE000, end));
+                end = 0xD7FF;
+            }
+
+            // split into ranges of chars with the same encoded length
+            for &max in &[0x7F, 0x7FF, 0xFFFF] {
+                if start <= max && end > max {
+                    stack.push((max + 1, end));
+                    end = max;
+                }
+            }
+
+            // split until every byte of start and end differ only in a
+            // shared prefix followed by full continuation byte ranges
+            for i in 1..4 {
+                let mask = (1_u32 << (6 * i)) - 1;
+                if start & !mask != end & !mask {
+                    if start & mask != 0 {
+                        stack.push(((start | mask) + 1, end));
+                        end = start | mask;
+                        continue 'split;
+                    }
+                    if end & mask != mask {
+                        stack.push((end & !mask, end));
+                        end = (end & !mask) - 1;
+                        continue 'split;
+                    }
+                }
             }
-        };
-    
-        if optional { regex.opt() } else { regex }
+
+            break;
+        }
+
+        if let (Some(a), Some(b)) = (std::char::from_u32(start), std::char::from_u32(end)) {
+            let (mut a_buffer, mut b_buffer) = ([0; 4], [0; 4]);
+            let a = a.encode_utf8(&mut a_buffer).bytes();
+            let b = b.encode_utf8(&mut b_buffer).bytes();
+
+            // both ends have the same length, and each byte position forms a range
+            regex = regex.or(&a.zip(b).fold(RegEx::empty(), |r, (low, high)| {
+                r.then(&RegEx::set(CharSet::range(low, high)))
+            }));
+        }
     }
-    
-    range32(from as u32, to as u32, false)
-}
 
+    regex
+}
@@ -0,0 +1,69 @@
+use std::collections::{BTreeMap, HashMap};
+use super::{RENode, RENodeRef};
+
+/// Memoizes the derivatives of a `RegEx`, so that matching computes each
+/// distinct derivative w.r.t. each byte at most once, i.e. lazily builds
+/// the states of a `DFA` as they are visited. The cache is flushed once it
+/// grows beyond a fixed size to bound memory usage.
+pub struct DerivCache {
+    nodes: Vec<RENodeRef>,
+    ids:   BTreeMap<RENodeRef, usize>,
+    next:  HashMap<(usize, u8), usize>,
+}
+
+impl DerivCache {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            ids:   BTreeMap::new(),
+            next:  HashMap::new(),
+        }
+    }
+
+    /// Returns the id of `node`, adding it to the cache if necessary.
+    pub fn intern(&mut self, node: &RENodeRef) -> usize {
+        if let Some(&id) = self.ids.get(node) {
+            id
+        } else {
+            let id = self.nodes.len();
+            self.nodes.push(node.clone());
+            self.ids.insert(node.clone(), id);
+            id
+        }
+    }
+
+    /// Returns the id of the derivative of node `id` w.r.t. `a`.
+    pub fn step(&mut self, id: usize, a: u8) -> usize {
+        if let Some(&next_id) = self.next.get(&(id, a)) {
+            return next_id;
+        }
+
+        let node = self.nodes[id].deriv(a);
+
+        if self.nodes.len() >= MAX_CACHED_NODES {
+            self.nodes.clear();
+            self.ids.clear();
+            self.next.clear();
+            self.intern(&node)
+        } else {
+            let next_id = self.intern(&node);
+            self.next.insert((id, a), next_id);
+            next_id
+        }
+    }
+
+    pub fn is_nullable(&self, id: usize) -> bool {
+        self.nodes[id].is_nullable()
+    }
+
+    /// Checks if node `id` is syntactically empty, so cannot match anything.
+    pub fn is_dead(&self, id: usize) -> bool {
+        matches!(self.nodes[id].as_ref(), RENode::None)
+    }
+}
+
+// =================
+// === INTERNALS ===
+// =================
+
+const MAX_CACHED_NODES: usize = 4096;
@@ -6,10 +6,12 @@ use std::iter::once;
 use std::fmt::Formatter;
 use std::fmt::Error;
 use std::fmt::Debug;
+use std::ops::Range;
 
 use crate::iter::IteratorExtensions;
 use crate::debug::StringBuilder;
-use super::CharSet;
+use super::{CharSet, DFA, PrefixStatus};
+use self::cache::DerivCache;
 
 #[derive(PartialEq, Eq, PartialOrd, Ord)]
 pub struct RegEx {
@@ -83,6 +85,62 @@ impl RegEx {
         Self { root: self.root.deriv(a) }
     }
 
+    /// Checks if `text` is recognized by this `RegEx`, by repeatedly taking
+    /// derivatives rather than constructing a `DFA` upfront.
+    #[must_use]
+    pub fn matches(&self, text: &str) -> bool {
+        let mut cache = DerivCache::new();
+        let mut id = cache.intern(&self.root);
+
+        for byte in text.bytes() {
+            id = cache.step(id, byte);
+            if cache.is_dead(id) {
+                return false;
+            }
+        }
+
+        cache.is_nullable(id)
+    }
+
+    /// Finds the leftmost-longest substring of `text` recognized by this
+    /// `RegEx`, by repeatedly taking derivatives rather than constructing a
+    /// `DFA` upfront.
+    #[must_use]
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        let bytes = text.as_bytes();
+        let mut cache = DerivCache::new();
+
+        for start in 0..=bytes.len() {
+            let mut id = cache.intern(&self.root);
+            let mut end = if cache.is_nullable(id) { Some(start) } else { None };
+
+            for (index, &byte) in bytes.iter().enumerate().skip(start) {
+                id = cache.step(id, byte);
+                if cache.is_dead(id) {
+                    break;
+                }
+                if cache.is_nullable(id) {
+                    end = Some(index + 1);
+                }
+            }
+
+            if let Some(end) = end {
+                return Some(start..end);
+            }
+        }
+
+        None
+    }
+
+    /// Checks if `text` is a prefix of some string recognized by this `RegEx`.
+    /// Each call constructs a `DFA`, so to check many prefixes of the same
+    /// `RegEx`, such as the input to a form field on each keystroke, build
+    /// the `DFA` once and call `DFA::prefix_status` instead.
+    #[must_use]
+    pub fn is_viable_prefix(&self, text: &str) -> bool {
+        DFA::from(self).prefix_status(text) != PrefixStatus::Dead
+    }
+
     #[must_use]
     pub fn dot(&self) -> String {
         let mut stack: Vec<(usize, &RENodeRef)> = vec![(0, &self.root)];
@@ -426,7 +484,7 @@ where
     }
 
     if let Some(first) = sets.pop() {
-        let re = RENodeRef::new(RENode::Set(sets.into_iter().fold(first.clone(), |acc, x| f(acc, x))));
+        let re = RENodeRef::new(RENode::Set(sets.into_iter().fold(*first, |acc, x| f(acc, x))));
         refs.into_iter().merge(once(re)).collect()
     } else {
         refs
@@ -478,5 +536,7 @@ impl Debug for RegEx {
     }
 }
 
+mod cache;
+
 #[cfg(test)]
 mod tests;
\ No newline at end of file
@@ -16,4 +16,87 @@ fn derivative() {
     let regex = re1.then(&re1).then(&re1).then(&re1);
     assert_eq!(regex.deriv(8).deriv(8).deriv(8).deriv(8), RegEx::empty());
     assert_eq!(regex.deriv(0), RegEx::none());
+}
+
+#[test]
+fn viable_prefix() {
+    let a = RegEx::set(CharSet::point(b'a'));
+    let b = RegEx::set(CharSet::point(b'b'));
+    let regex = a.star().then(&b).and(&a.then(&a).star().then(&b));
+
+    assert!( regex.is_viable_prefix("") );
+    assert!( regex.is_viable_prefix("aaa") );
+    assert!( regex.is_viable_prefix("aab") );
+    assert!( !regex.is_viable_prefix("ab") );
+    assert!( !regex.is_viable_prefix("aabb") );
+}
+
+#[test]
+fn utf8_ranges() {
+    use crate::lang::re::{self, DFA};
+
+    let ranges = [('a', 'z'), ('\u{7F}', '\u{80}'), ('\u{7FF}', '\u{800}'), ('\u{A0}', '\u{D7FF}'), ('\u{D7FF}', '\u{E000}'), ('\u{E000}', '\u{FDCF}'), ('\u{5B}', '\u{10FA3}'), ('\u{1F600}', '\u{1F64F}'), ('\u{0}', '\u{10FFFF}')];
+    let samples = ['\u{0}', 'Z', '[', 'a', 'z', '\u{7F}', '\u{80}', '\u{9F}', '\u{A0}', '\u{7FF}', '\u{800}', '\u{D7FF}', '\u{E000}', '\u{FDCF}', '\u{FDD0}', '\u{FFFF}', '\u{10000}', '\u{10FA3}', '\u{10FA4}', '\u{1F5FF}', '\u{1F600}', '\u{1F64F}', '\u{1F650}', '\u{10FFFF}'];
+
+    for &(from, to) in &ranges {
+        let dfa = DFA::from(&re::range(from, to));
+        for &c in &samples {
+            assert_eq!(dfa.matches(&c.to_string()), from <= c && c <= to, "{:?} in {:?}..={:?}", c, from, to);
+        }
+        assert!(!dfa.matches(""));
+        assert!(!dfa.matches(&format!("{}{}", from, to)));
+    }
+
+    // bounds may come in either order
+    let dfa = DFA::from(&re::range('\u{1F64F}', '\u{1F600}'));
+    assert!(dfa.matches("\u{1F610}"));
+}
+
+#[test]
+fn derivative_matching() {
+    use crate::lang::re::DFA;
+
+    let mut rng = Lcg(0x5EED);
+
+    for _ in 0..200 {
+        let regex = random_regex(&mut rng, 4);
+        let dfa = DFA::from(&regex);
+
+        for _ in 0..20 {
+            let len = rng.next(8);
+            let text = (0..len).map(|_| ['a', 'b', 'c'][rng.next(3)]).collect::<String>();
+
+            assert_eq!(regex.matches(&text), dfa.matches(&text), "{:?} on {:?}", regex, text);
+            assert_eq!(regex.find(&text), dfa.find(&text), "{:?} on {:?}", regex, text);
+        }
+    }
+}
+
+// =================
+// === UTILITIES ===
+// =================
+
+/// Deterministic pseudo-random number generator.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, n: usize) -> usize {
+        self.0 = self.0.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        (self.0 >> 33) as usize % n
+    }
+}
+
+fn random_regex(rng: &mut Lcg, depth: usize) -> RegEx {
+    let choice = if depth == 0 { rng.next(3) } else { rng.next(9) };
+    match choice {
+        0 => RegEx::set(CharSet::point(b'a' + rng.next(3) as u8)),
+        1 => RegEx::set(CharSet::range(b'a', b'b')),
+        2 => RegEx::empty(),
+        3 => random_regex(rng, depth - 1).then(&random_regex(rng, depth - 1)),
+        4 => random_regex(rng, depth - 1).or(&random_regex(rng, depth - 1)),
+        5 => random_regex(rng, depth - 1).and(&random_regex(rng, depth - 1)),
+        6 => random_regex(rng, depth - 1).star(),
+        7 => random_regex(rng, depth - 1).not(),
+        _ => random_regex(rng, depth - 1).plus(),
+    }
 }
\ No newline at end of file
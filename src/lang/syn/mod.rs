@@ -1,7 +1,7 @@
 #![allow(non_snake_case)]
 
 pub use self::compile::{SynDef, CompileError};
-pub use self::parse::{Node, Parse, ParseError};
+pub use self::parse::{Node, Parse, ParseError, ParseErrorSource};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Action {
@@ -26,6 +26,17 @@ pub struct ParseError {
 pub enum ParseErrorSource {
     InvalidAction { word: Option<usize> },
     InvalidGoto   { var: usize },
+    /// The words ended where any of the `expected` words could continue them.
+    Incomplete    { expected: Vec<usize> },
+}
+
+impl ParseError {
+    /// Checks if more words could complete the input, e.g. so a REPL can read
+    /// another line rather than report an error.
+    #[must_use]
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self.source, ParseErrorSource::Incomplete { .. })
+    }
 }
 
 impl<'a, I: Iterator<Item=usize>> Parse<'a, I> {
@@ -48,11 +59,22 @@ impl<'a, I: Iterator<Item=usize>> Iterator for Parse<'a, I> {
     fn next(&mut self) -> Option<Self::Item> {
         match self.next_action {
             Action::Invalid => {
+                let state = *self.state_history.last().unwrap();
+
+                // words that could be shifted or reduced on at the end of input
+                let expected = if self.curr_word.is_none() {
+                    (0..self.syn.word_count).filter(|&word| !matches!(self.syn.action(state, Some(word)), Action::Invalid)).collect()
+                } else {
+                    Vec::new()
+                };
+
                 Some(Err(ParseError {
                     step: self.step,
-                    state: *self.state_history.last().unwrap(),
-                    source: ParseErrorSource::InvalidAction {
-                        word: self.curr_word
+                    state,
+                    source: if expected.is_empty() {
+                        ParseErrorSource::InvalidAction { word: self.curr_word }
+                    } else {
+                        ParseErrorSource::Incomplete { expected }
                     },
                 }))
             },
@@ -68,6 +68,33 @@ fn parentheses_grammar_2() {
     // println!("{:?}", parse.unwrap());
 }
 
+#[test]
+fn incomplete_input() {
+    let parser = syn_def! {
+        { open, close }
+        List : List Pair
+             | Pair,
+        Pair : open List close
+             | open close,
+    }.1.compile().unwrap();
+
+    let error = |input: &[usize]| parser.parse(input.iter().cloned()).find_map(Result::err);
+
+    // input that ends early could be completed by more words
+    for input in &[&[][..], &[0], &[0, 0, 1]] {
+        assert!(error(input).unwrap().is_incomplete(), "Input {:?} is incomplete", input);
+    }
+    match error(&[0]).unwrap().source {
+        super::ParseErrorSource::Incomplete { expected } => assert_eq!(expected, &[0, 1]),
+        source => panic!("unexpected {:?}", source),
+    }
+
+    // other errors are not
+    assert!(!error(&[1]).unwrap().is_incomplete());
+    assert!(!error(&[0, 1, 1]).unwrap().is_incomplete());
+    assert!(error(&[0, 1]).is_none());
+}
+
 // #[test]
 // fn parse_tree() {
 //     let (lex_grammar, syn_grammar) = crate::meta::make_grammars();
@@ -10,5 +10,6 @@ pub mod iter;
 
 pub mod lang;
 pub mod cst;
+pub mod highlight;
 
 pub mod debug;
\ No newline at end of file
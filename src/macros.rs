@@ -20,35 +20,105 @@ macro_rules! words {
 
 #[macro_export]
 macro_rules! lex_def {
-    (@accum $out:tt $count:expr ; [$($body:tt)*] $label:ident : $regex:expr , $($tail:tt)+) => {
-        $crate::lex_def![@accum $out $count + 1_usize ; [$($body)* $count , emit $label $regex;] $($tail)*]
+    // nested and scanned rules come first, as `nested(..)` and `scan(..)` would also parse as regexes
+    (@accum $out:tt $count:expr ; [$($body:tt)*] < $($mode:ident),+ > [$command:ident $(($($arg:tt)*))? $(, $change:ident $(($target:ident))?)?] $label:ident : nested ($open:expr , $close:expr) , $($tail:tt)+) => {
+        $crate::lex_def![@accum $out $count + 1_usize ; [$($body)* $count , ($($mode),+) $command ($($($arg)*)?) ($($change $($target)?)?) () (nested $open , $close) $label $crate::lang::re::literal($open) ;] $($tail)*]
     };
-    (@accum $out:tt $count:expr ; [$($body:tt)*] [$command:ident] $label:ident : $regex:expr , $($tail:tt)+) => {
-        $crate::lex_def![@accum $out $count + 1_usize ; [$($body)* $count , $command $label $regex ;] $($tail)*]
+    (@accum $out:tt $count:expr ; [$($body:tt)*] < $($mode:ident),+ > [$command:ident $(($($arg:tt)*))? $(, $change:ident $(($target:ident))?)?] $label:ident : nested ($open:expr , $close:expr) $(,)?) => {
+        $crate::lex_def![@fin $out $count + 1_usize ; $($body)* $count , ($($mode),+) $command ($($($arg)*)?) ($($change $($target)?)?) () (nested $open , $close) $label $crate::lang::re::literal($open)]
     };
-    (@accum $out:tt $count:expr ; [$($body:tt)*] $label:ident : $regex:expr $(,)?) => {
-        $crate::lex_def![@fin $out $count + 1_usize ; $($body)* $count , emit $label $regex]
+    (@accum $out:tt $count:expr ; [$($body:tt)*] < $($mode:ident),+ > [$command:ident $(($($arg:tt)*))? $(, $change:ident $(($target:ident))?)?] $label:ident : scan ($prefix:expr , $scanner:expr) , $($tail:tt)+) => {
+        $crate::lex_def![@accum $out $count + 1_usize ; [$($body)* $count , ($($mode),+) $command ($($($arg)*)?) ($($change $($target)?)?) () (scan $scanner) $label $prefix ;] $($tail)*]
     };
-    (@accum $out:tt $count:expr ; [$($body:tt)*] [$command:ident] $label:ident : $regex:expr $(,)?) => {
-        $crate::lex_def![@fin $out $count + 1_usize ; $($body)* $count , $command $label $regex]
+    (@accum $out:tt $count:expr ; [$($body:tt)*] < $($mode:ident),+ > [$command:ident $(($($arg:tt)*))? $(, $change:ident $(($target:ident))?)?] $label:ident : scan ($prefix:expr , $scanner:expr) $(,)?) => {
+        $crate::lex_def![@fin $out $count + 1_usize ; $($body)* $count , ($($mode),+) $command ($($($arg)*)?) ($($change $($target)?)?) () (scan $scanner) $label $prefix]
     };
-    (@command emit) => { $crate::lang::lex::Command::Emit };
-    (@command skip) => { $crate::lang::lex::Command::Skip };
-    (@fin _ $count:expr ; $($id:expr , $command:ident $label:ident $regex:expr);+) => {
+    (@accum $out:tt $count:expr ; [$($body:tt)*] < $($mode:ident),+ > [$command:ident $(($($arg:tt)*))? $(, $change:ident $(($target:ident))?)?] $label:ident : $regex:expr $(=> $value:ident $(($($value_arg:tt)*))?)? , $($tail:tt)+) => {
+        $crate::lex_def![@accum $out $count + 1_usize ; [$($body)* $count , ($($mode),+) $command ($($($arg)*)?) ($($change $($target)?)?) ($($value ($($($value_arg)*)?))?) () $label $regex ;] $($tail)*]
+    };
+    (@accum $out:tt $count:expr ; [$($body:tt)*] < $($mode:ident),+ > [$command:ident $(($($arg:tt)*))? $(, $change:ident $(($target:ident))?)?] $label:ident : $regex:expr $(=> $value:ident $(($($value_arg:tt)*))?)? $(,)?) => {
+        $crate::lex_def![@fin $out $count + 1_usize ; $($body)* $count , ($($mode),+) $command ($($($arg)*)?) ($($change $($target)?)?) ($($value ($($($value_arg)*)?))?) () $label $regex]
+    };
+    // rules emit by default
+    (@accum $out:tt $count:expr ; $body:tt < $($mode:ident),+ > $label:ident : $($tail:tt)+) => {
+        $crate::lex_def![@accum $out $count ; $body < $($mode),+ > [emit] $label : $($tail)+]
+    };
+    // rules are only active in the initial mode by default
+    (@accum $out:tt $count:expr ; $body:tt [$($command:tt)*] $($tail:tt)+) => {
+        $crate::lex_def![@accum $out $count ; $body <INITIAL> [$($command)*] $($tail)+]
+    };
+    (@accum $out:tt $count:expr ; $body:tt $label:ident : $($tail:tt)+) => {
+        $crate::lex_def![@accum $out $count ; $body <INITIAL> [emit] $label : $($tail)+]
+    };
+    (@command $class_id:ident ; emit ()) => { $crate::lang::lex::Command::Emit };
+    (@command $class_id:ident ; skip ()) => { $crate::lang::lex::Command::Skip };
+    (@command $class_id:ident ; more ()) => { $crate::lang::lex::Command::More };
+    (@command $class_id:ident ; type ($label:ident)) => { $crate::lang::lex::Command::Type($class_id(stringify!($label))) };
+    (@command $class_id:ident ; error ($reason:expr)) => { $crate::lang::lex::Command::Error(String::from($reason)) };
+    (@command $class_id:ident ; callback ($callback:expr)) => { $crate::lang::lex::Command::Callback($callback) };
+    (@change $mode_id:ident ;) => { None };
+    (@change $mode_id:ident ; push $mode:ident) => { Some($crate::lang::lex::ModeChange::Push($mode_id(stringify!($mode)))) };
+    (@change $mode_id:ident ; pop) => { Some($crate::lang::lex::ModeChange::Pop) };
+    (@change $mode_id:ident ; switch $mode:ident) => { Some($crate::lang::lex::ModeChange::Switch($mode_id(stringify!($mode)))) };
+    (@value ()) => { None };
+    (@value (decimal ())) => { Some($crate::lang::lex::Converter::Decimal) };
+    (@value (hex ())) => { Some($crate::lang::lex::Converter::Hex) };
+    (@value (octal ())) => { Some($crate::lang::lex::Converter::Octal) };
+    (@value (float ())) => { Some($crate::lang::lex::Converter::Float) };
+    (@value (string ($escapes:expr))) => { Some($crate::lang::lex::Converter::Str($escapes)) };
+    (@value (char ($escapes:expr))) => { Some($crate::lang::lex::Converter::Char($escapes)) };
+    (@value (custom ($converter:expr))) => { Some($crate::lang::lex::Converter::Custom($converter)) };
+    (@nested (nested $open:expr , $close:expr)) => { Some($crate::lang::lex::Nested { open: String::from($open), close: String::from($close) }) };
+    (@nested $kind:tt) => { None };
+    (@scanner (scan $scanner:expr)) => { Some::<$crate::lang::lex::Scanner>($scanner) };
+    (@scanner $kind:tt) => { None };
+    (@def $($id:expr , ($($mode:ident),+) $command:ident $args:tt ($($change:tt)*) $value:tt $kind:tt $label:ident $regex:expr);+) => {
         {
-            let lex_def = $crate::lang::lex::LexDef {
+            let labels = [$(stringify!($label)),+];
+            #[allow(unused_variables)]
+            let class_id = |label: &str| labels.iter().position(|&other| other == label)
+                .unwrap_or_else(|| panic!("undefined token class {}", label));
+
+            // modes are numbered in order of first use, after the initial mode
+            let rule_modes: &[&[&str]] = &[$(&[$(stringify!($mode)),+]),+];
+            let mut mode_names = vec!["INITIAL"];
+            for &name in rule_modes.iter().copied().flatten() {
+                if !mode_names.contains(&name) {
+                    mode_names.push(name);
+                }
+            }
+
+            let mode_id = |name: &str| mode_names.iter().position(|&other| other == name)
+                .unwrap_or_else(|| panic!("lexer mode {} has no rules", name));
+
+            let mut modes = vec![Vec::new(); mode_names.len()];
+            for (class, names) in rule_modes.iter().enumerate() {
+                for &name in names.iter() {
+                    modes[mode_id(name)].push(class);
+                }
+            }
+
+            $crate::lang::lex::LexDef {
                 regexes: vec![$($regex),+],
-                commands: vec![$($crate::lex_def![@command $command]),+],
-            };
+                commands: vec![$($crate::lex_def![@command class_id ; $command $args]),+],
+                mode_changes: vec![$($crate::lex_def![@change mode_id ; $($change)*]),+],
+                converters: vec![$($crate::lex_def![@value $value]),+],
+                nested: vec![$($crate::lex_def![@nested $kind]),+],
+                scanners: vec![$($crate::lex_def![@scanner $kind]),+],
+                layout: None,
+                modes,
+            }
+        }
+    };
+    (@fin _ $count:expr ; $($id:expr , $modes:tt $command:ident $args:tt $change:tt $value:tt $kind:tt $label:ident $regex:expr);+) => {
+        {
+            let lex_def = $crate::lex_def![@def $($id , $modes $command $args $change $value $kind $label $regex);+];
             (vec![$(stringify!($label).to_string()),+], lex_def)
         }
     };
-    (@fin $out:ident $count:expr ; $($id:expr , $command:ident $label:ident $regex:expr);+) => {
+    (@fin $out:ident $count:expr ; $($id:expr , $modes:tt $command:ident $args:tt $change:tt $value:tt $kind:tt $label:ident $regex:expr);+) => {
         let $out = {
-            let lex_def = $crate::lang::lex::LexDef {
-                regexes: vec![$($regex),+],
-                commands: vec![$($crate::lex_def![@command $command]),+],
-            };
+            let lex_def = $crate::lex_def![@def $($id , $modes $command $args $change $value $kind $label $regex);+];
             (vec![$(stringify!($label).to_string()),+], lex_def)
         };
 
@@ -126,14 +196,69 @@ macro_rules! parser_def {
                 syn_labels,
                 lex_def: $lex_def.1,
                 syn_def: __SYN_DEF__,
-                commands: vec![$($commands)*]
+                commands: vec![$($commands)*],
+                rewrites: Vec::new(),
             }
         }
     };
-    (lexer : { $($lexer:tt)* } , parser : { $($parser:tt)* } $(,)?) => {
+    (@layout $labels:expr ; indent : $indent:ident , dedent : $dedent:ident , newline : $newline:ident $(, tab_width : $tab_width:expr)? $(, brackets : [$(($open:ident , $close:ident)),* $(,)?])? $(,)?) => {
+        {
+            let class = |label: &str| $labels.iter().position(|other| other == label)
+                .unwrap_or_else(|| panic!("undefined token class {}", label));
+
+            $crate::lang::lex::Layout {
+                indent: class(stringify!($indent)),
+                dedent: class(stringify!($dedent)),
+                newline: class(stringify!($newline)),
+                tab_width: { let tab_width = 8_usize; $(let tab_width = $tab_width;)? tab_width },
+                brackets: vec![$($((class(stringify!($open)), class(stringify!($close)))),*)?],
+            }
+        }
+    };
+    (@rewrites $labels:expr ; $($rules:tt)*) => {
+        {
+            let class = |label: &str| $labels.iter().position(|other| other == label)
+                .unwrap_or_else(|| panic!("undefined token class {}", label));
+
+            $crate::parser_def![@rewrite class ; [] $($rules)*]
+        }
+    };
+    (@rewrite $class:ident ; [$($rules:tt)*] insert $inserted:ident after [$($after:ident),* $(,)?] $(, $($tail:tt)*)?) => {
+        $crate::parser_def![@rewrite $class ; [$($rules)* $crate::lang::parser::Rewrite::Insert {
+            class: $class(stringify!($inserted)),
+            after: vec![$($class(stringify!($after))),*],
+        },] $($($tail)*)?]
+    };
+    (@rewrite $class:ident ; [$($rules:tt)*] drop $dropped:ident $(before [$($before:ident),* $(,)?])? $(, $($tail:tt)*)?) => {
+        $crate::parser_def![@rewrite $class ; [$($rules)* $crate::lang::parser::Rewrite::Drop {
+            class: $class(stringify!($dropped)),
+            before: vec![$($($class(stringify!($before))),*)?],
+        },] $($($tail)*)?]
+    };
+    (@rewrite $class:ident ; [$($rules:tt)*] merge $first:ident $second:ident into $into:ident $(, $($tail:tt)*)?) => {
+        $crate::parser_def![@rewrite $class ; [$($rules)* $crate::lang::parser::Rewrite::Merge {
+            first: $class(stringify!($first)),
+            second: $class(stringify!($second)),
+            into: $class(stringify!($into)),
+        },] $($($tail)*)?]
+    };
+    (@rewrite $class:ident ; [$($rules:tt)*]) => {
+        vec![$($rules)*]
+    };
+    (lexer : { $($lexer:tt)* } , $(layout : { $($layout:tt)* } ,)? $(rewrite : { $($rewrite:tt)* } ,)? parser : { $($parser:tt)* } $(,)?) => {
         {
             $crate::lex_def![@accum __LEX_DEF__ 0_usize ; [] $($lexer)*];
-            $crate::parser_def![@accum __LEX_DEF__ {} {} $($parser)*]
+            $(
+                let mut __LEX_DEF__ = __LEX_DEF__;
+                __LEX_DEF__.1.layout = Some($crate::parser_def![@layout __LEX_DEF__.0 ; $($layout)*]);
+            )?
+
+            #[allow(unused_mut)]
+            let mut __PARSER_DEF__ = $crate::parser_def![@accum __LEX_DEF__ {} {} $($parser)*];
+            $(
+                __PARSER_DEF__.rewrites = $crate::parser_def![@rewrites __PARSER_DEF__.lex_labels ; $($rewrite)*];
+            )?
+            __PARSER_DEF__
         }
     };
 }
\ No newline at end of file